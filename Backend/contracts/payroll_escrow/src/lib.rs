@@ -1,22 +1,21 @@
-/**
- * PayrollEscrow Smart Contract
- * 
- * A Soroban smart contract for managing payroll escrows with support for:
- * - Creating payroll escrows with multiple recipients
- * - Depositing funds from employers
- * - Releasing payments to recipients (immediate, scheduled, or streaming)
- * - Emergency controls and access management
- * 
- * Security Features:
- * - Only employer or authorized addresses can fund/release
- * - Circuit breaker for emergency stops
- * - Audit events for all critical operations
- */
+//! PayrollEscrow Smart Contract
+//!
+//! A Soroban smart contract for managing payroll escrows with support for:
+//! - Creating payroll escrows with multiple recipients
+//! - Depositing funds from employers
+//! - Releasing payments to recipients (immediate, scheduled, or streaming)
+//! - Emergency controls and access management
+//!
+//! Security Features:
+//! - Only employer or authorized addresses can fund/release
+//! - Circuit breaker for emergency stops
+//! - Audit events for all critical operations
 
 #![no_std]
+#![allow(clippy::too_many_arguments)]
 use soroban_sdk::{
     contract, contractimpl, contracttype, contracterror, contractmeta,
-    Address, Env, String, Vec, Map, BytesN
+    Address, Bytes, BytesN, Env, Map, String, Vec
 };
 
 // Contract metadata
@@ -50,6 +49,21 @@ pub struct Employee {
     pub status: EmployeeStatus,
     pub created_at: u64,
     pub updated_at: u64,
+    pub start_date: u64, // Start of the current period of employment
+}
+
+/// One record of a bulk `add_employees` import
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NewEmployee {
+    pub wallet_address: Address,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub position: String,
+    pub salary: u64,
+    pub currency: String,
+    pub payment_schedule: String,
 }
 
 #[contracttype]
@@ -60,6 +74,175 @@ pub enum EmployeeStatus {
     Terminated,
 }
 
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EmployeeChange {
+    Salary(u64, u64), // (old, new)
+    Position(String, String),
+    PaymentSchedule(String, String),
+    Status(EmployeeStatus, EmployeeStatus),
+    Wallet(Address, Address),
+    SalaryChangeCancelled(u64, u64), // Scheduled (salary, effective_at) that never applied
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EmployeeHistoryEntry {
+    pub change: EmployeeChange,
+    pub effective_at: u64,
+    pub changed_by: Address,
+    pub recorded_at: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingSalaryChange {
+    pub salary: u64,
+    pub effective_at: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FinalPayMode {
+    Pay, // Paid to the employee's wallet straight away
+    Escrow, // Placed in a new payroll for the employer to fund and release
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OffboardingSummary {
+    pub employee_id: u64,
+    pub streams_cancelled: u32,
+    pub stream_settled: u64, // Accrued stream funds paid to the employee
+    pub stream_refunded: u64, // Unaccrued stream funds returned to the employer
+    pub final_pay: u64,
+    pub final_pay_payroll: Option<u64>, // Set when final pay is escrowed
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EmployeePage {
+    pub employees: Vec<Employee>,
+    pub next_cursor: Option<u32>, // None once the list is exhausted
+}
+
+/// Payrolls created from one page of an employer's employees
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EmployeePayrollPage {
+    pub payroll_ids: Vec<u64>,
+    pub next_cursor: Option<u32>, // None once the list is exhausted
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SplitShare {
+    Percent(u32), // Basis points of the gross payment
+    Fixed(u64),
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PayoutAsset {
+    Source, // Whatever asset the payroll or stream pays in
+    Other(Address),
+}
+
+/// Part of an employee's pay sent somewhere other than their wallet
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PayoutSplit {
+    pub destination: Address,
+    pub asset: PayoutAsset,
+    pub share: SplitShare,
+}
+
+/// One leg of a split payment
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Payout {
+    pub employee_id: u64,
+    pub destination: Address,
+    pub asset: PayoutAsset,
+    pub amount: u64,
+}
+
+/// How much of their earned wages an employer's workers may draw early
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AdvancePolicy {
+    pub max_percent: u32, // Basis points of wages accrued so far this period
+    pub period_cap: u64, // Most that can be advanced against one payroll
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DeductionKind {
+    Tax,
+    Pension,
+    Benefits,
+    Other(String),
+}
+
+/// Part of every payment an employer withholds and sends elsewhere
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeductionRule {
+    pub kind: DeductionKind,
+    pub share: SplitShare, // Percentages are of the gross payment
+    pub destination: Address, // Withholding account, e.g. the tax authority
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeductionLine {
+    pub kind: DeductionKind,
+    pub destination: Address,
+    pub amount: u64,
+}
+
+/// Breakdown of one released payment
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Payslip {
+    pub payroll_id: u64,
+    pub recipient: Address,
+    pub gross: u64,
+    pub deductions: Vec<DeductionLine>,
+    pub advance: u64, // Drawn early and already paid
+    pub net: u64, // Paid to the recipient at release
+    pub shortfall: u64, // Deductions the advance left no room to withhold
+    pub paid_at: u64,
+}
+
+/// An unpaid amount owed to a worker by a payroll that can still pay out
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PayrollClaim {
+    pub payroll_id: u64,
+    pub amount: u64,
+    pub asset: Address,
+    pub status: PayrollStatus,
+    pub release_time: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamClaim {
+    pub stream_id: u64,
+    pub asset: Address,
+    pub balance: StreamBalance,
+}
+
+/// A worker's record with one employer and what that employer owes them
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Employment {
+    pub employee: Employee,
+    pub payroll_claims: Vec<PayrollClaim>,
+    pub streams: Vec<StreamClaim>, // Active streams from the employer
+}
+
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum PayrollStatus {
@@ -93,6 +276,15 @@ pub struct PayrollData {
     pub release_time: u64, // Unix timestamp
     pub created_at: u64,
     pub stream_rate: Option<u64>, // Tokens per second for streaming
+    pub release_cursor: u32, // Index of the next recipient to release
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PayrollAmendment {
+    AddRecipient(Address, u64),
+    RemoveRecipient(Address),
+    SetAmount(Address, u64),
 }
 
 #[contracttype]
@@ -107,6 +299,56 @@ pub struct StreamData {
     pub total_deposited: u64,
     pub total_withdrawn: u64,
     pub active: bool,
+    pub payroll_id: Option<u64>, // Set when opened by a streaming payroll
+    pub cliff_time: u64, // Nothing is withdrawable before this timestamp
+    pub curve: VestingCurve,
+    pub settled_amount: u64, // Accrued at earlier rates up to start_time
+    pub paused: bool,
+    pub paused_at: u64, // When the current pause began
+    pub transferable: bool, // Whether `to` may hand the stream to another address
+    pub asset: Address, // Asset contract the stream pays in
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamSpec {
+    pub to: Address,
+    pub rate_per_sec: u64,
+    pub duration: u64,
+    pub total_amount: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamBalance {
+    pub accrued: u64, // Vested so far, including withdrawn funds
+    pub withdrawn: u64,
+    pub withdrawable: u64,
+    pub remaining: u64, // Deposited but not yet withdrawn
+    pub time_to_depletion: u64, // Seconds until everything has vested
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tranche {
+    pub unlock_time: u64,
+    pub amount: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VestingCurve {
+    Linear,
+    Stepped(u64), // Unlocks rate * interval at the end of each interval
+    Tranches(Vec<Tranche>), // Fixed amounts unlocking at set times
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamSchedule {
+    pub start_time: Option<u64>, // Defaults to the current ledger time
+    pub cliff_time: Option<u64>,
+    pub curve: VestingCurve,
 }
 
 #[contracterror]
@@ -125,6 +367,53 @@ pub enum Error {
     StreamInactive = 10,
     InvalidAmount = 11,
     TooEarly = 12,
+    DuplicateRecipient = 13,
+    ZeroRecipientAmount = 14,
+    RecipientAlreadyPaid = 15,
+    RecipientStreamPreset = 16,
+    TooManyRecipients = 17,
+    PayrollNotAmendable = 18,
+    RecipientNotFound = 19,
+    InvalidSchedule = 20,
+    StreamPaused = 21,
+    StreamNotPaused = 22,
+    StreamNotTransferable = 23,
+    InvalidStatusTransition = 24,
+    DuplicateEmployee = 25,
+    UnmappedCurrency = 26,
+    InvalidSplit = 27,
+    AdvanceNotAvailable = 28,
+    InvalidDeduction = 29,
+    AdvanceOutstanding = 30,
+    PayrollReleasing = 31,
+    InvalidEmail = 32,
+}
+
+/// Persistent storage keys for per-record data
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DataKey {
+    Stream(u64),
+    RecipientStreams(Address), // Active streams paying an address
+    EmployerPayrolls(Address),
+    RecipientPayrolls(Address), // Payrolls that still owe an address
+    EmployeeCount(Address, EmployeeStatus),
+    EmployeeHistory(u64, u32), // (employee id, page)
+    EmployeeHistoryLen(u64),
+    PendingSalary(u64),
+    PendingWallet(u64), // New wallet awaiting the employee's confirmation
+    Employee(u64),
+    EmployerEmployees(Address),
+    EmployeeByWallet(Address, Address), // (employer, wallet) -> employee id
+    EmployeeByEmail(Address, BytesN<32>), // (employer, email hash) -> employee id
+    WalletEmployees(Address), // Employee ids paid to a wallet, across employers
+    CurrencyAsset(Address, String), // (employer, currency code) -> asset contract
+    PayoutSplits(u64),
+    SplitEmployeeCount(Address), // Employees of an employer with payout splits
+    AdvancePolicy(Address),
+    Advance(u64, Address), // (payroll id, recipient) -> amount advanced
+    DeductionRules(Address),
+    Payslip(u64, Address), // (payroll id, recipient)
 }
 
 // Storage keys
@@ -134,6 +423,31 @@ const EMPLOYEE_COUNTER: &str = "EMPLOYEE_CTR";
 const CIRCUIT_BREAKER: &str = "BREAKER";
 const ADMIN: &str = "ADMIN";
 
+/// Upper bound on recipients in a single payroll
+pub const MAX_RECIPIENTS: u32 = 1000;
+
+/// Number of entries stored per page of an employee's history
+pub const HISTORY_PAGE_SIZE: u32 = 20;
+
+/// Upper bound on streams created by a single `start_streams` call, sized to
+/// stay well inside the default network CPU and memory budget
+pub const MAX_STREAM_BATCH: u32 = 50;
+
+/// Upper bound on employees created by a single `add_employees` call
+pub const MAX_EMPLOYEE_BATCH: u32 = 50;
+
+/// Longest email address accepted for an employee
+pub const MAX_EMAIL_LEN: u32 = 254;
+
+/// Upper bound on payout splits per employee
+pub const MAX_PAYOUT_SPLITS: u32 = 5;
+
+/// Upper bound on deduction rules per employer
+pub const MAX_DEDUCTION_RULES: u32 = 10;
+
+/// Basis points in 100%
+const BASIS_POINTS: u64 = 10_000;
+
 #[contract]
 pub struct PayrollEscrowContract;
 
@@ -174,46 +488,96 @@ impl PayrollEscrowContract {
             return Err(Error::CircuitBreakerActive);
         }
 
-        // Validate recipients
-        if recipients.is_empty() {
-            return Err(Error::InvalidRecipients);
+        store_payroll(&env, employer, recipients, asset, schedule_type, release_time, stream_rate)
+    }
+
+    /// Create payrolls paying one period's salary to the active employees
+    /// on `payment_schedule`, reading at most `limit` employee records
+    /// starting at `cursor`. Employees are grouped by the asset their
+    /// currency maps to, giving one payroll per asset; payroll ids are in
+    /// order of each asset's first employee. A page with no matching
+    /// employees creates no payrolls.
+    pub fn create_employee_payrolls(
+        env: Env,
+        employer: Address,
+        payment_schedule: String,
+        cursor: u32,
+        limit: u32,
+        schedule_type: ScheduleType,
+        release_time: Option<u64>,
+        stream_rate: Option<u64>,
+    ) -> Result<EmployeePayrollPage, Error> {
+        employer.require_auth();
+
+        // Check circuit breaker
+        let breaker_active: bool = env.storage().instance().get(&CIRCUIT_BREAKER).unwrap_or(false);
+        if breaker_active {
+            return Err(Error::CircuitBreakerActive);
         }
 
-        // Calculate total amount
-        let total_amount: u64 = recipients.iter().map(|r| r.amount).sum();
-        if total_amount == 0 {
+        // Each page must fit in a single payroll per asset
+        if limit == 0 {
             return Err(Error::InvalidAmount);
         }
+        if limit > MAX_RECIPIENTS {
+            return Err(Error::TooManyRecipients);
+        }
 
-        // Get next payroll ID
-        let mut counter: u64 = env.storage().instance().get(&PAYROLL_COUNTER).unwrap_or(0);
-        counter += 1;
-        env.storage().instance().set(&PAYROLL_COUNTER, &counter);
-
-        // Create payroll data
-        let payroll = PayrollData {
-            employer: employer.clone(),
-            recipients,
-            total_amount,
-            deposited_amount: 0,
-            asset,
-            status: PayrollStatus::Created,
-            schedule_type,
-            release_time: release_time.unwrap_or(0),
-            created_at: env.ledger().timestamp(),
-            stream_rate,
-        };
-
-        // Store payroll
-        env.storage().persistent().set(&counter, &payroll);
+        let employee_ids: Vec<u64> = env.storage().persistent()
+            .get(&DataKey::EmployerEmployees(employer.clone()))
+            .unwrap_or(Vec::new(&env));
+        let start = cursor.min(employee_ids.len());
+        let end = start.saturating_add(limit).min(employee_ids.len());
+
+        let mut assets: Vec<Address> = Vec::new(&env);
+        let mut currencies: Map<String, Address> = Map::new(&env);
+        let mut groups: Map<Address, Vec<Recipient>> = Map::new(&env);
+        for employee_id in employee_ids.slice(start..end).iter() {
+            let employee = load_employee(&env, employee_id)?;
+            if employee.status != EmployeeStatus::Active || employee.payment_schedule != payment_schedule {
+                continue;
+            }
+            let asset = match currencies.get(employee.currency.clone()) {
+                Some(asset) => asset,
+                None => {
+                    let asset = currency_asset(&env, &employer, &employee.currency)?;
+                    currencies.set(employee.currency.clone(), asset.clone());
+                    asset
+                }
+            };
+            let mut recipients = match groups.get(asset.clone()) {
+                Some(recipients) => recipients,
+                None => {
+                    assets.push_back(asset.clone());
+                    Vec::new(&env)
+                }
+            };
+            recipients.push_back(Recipient {
+                address: employee.wallet_address,
+                amount: employee.salary,
+                paid: false,
+                stream_id: None,
+            });
+            groups.set(asset, recipients);
+        }
 
-        // Emit event
-        env.events().publish(
-            ("payroll_created",),
-            (counter, employer, total_amount)
-        );
+        let mut payroll_ids = Vec::new(&env);
+        for asset in assets.iter() {
+            let recipients = groups.get(asset.clone()).unwrap();
+            let payroll_id = store_payroll(
+                &env,
+                employer.clone(),
+                recipients,
+                asset,
+                schedule_type.clone(),
+                release_time,
+                stream_rate,
+            )?;
+            payroll_ids.push_back(payroll_id);
+        }
 
-        Ok(counter)
+        let next_cursor = if end < employee_ids.len() { Some(end) } else { None };
+        Ok(EmployeePayrollPage { payroll_ids, next_cursor })
     }
 
     /// Deposit funds to a payroll escrow
@@ -273,67 +637,134 @@ impl PayrollEscrowContract {
         payroll_id: u64,
         caller: Address,
     ) -> Result<(), Error> {
-        caller.require_auth();
+        release_recipients(&env, payroll_id, caller, u32::MAX)?;
+        Ok(())
+    }
+
+    /// Release payments to the next `max_count` unpaid recipients
+    /// Large payrolls are paid across several calls; progress is kept in
+    /// `release_cursor` and the payroll stays `Releasing` until every
+    /// recipient has been paid. Returns the number of recipients still unpaid.
+    pub fn release_batch(
+        env: Env,
+        payroll_id: u64,
+        caller: Address,
+        max_count: u32,
+    ) -> Result<u32, Error> {
+        if max_count == 0 {
+            return Err(Error::InvalidAmount);
+        }
+        release_recipients(&env, payroll_id, caller, max_count)
+    }
+
+    /// Amend the recipients of a payroll that has not started releasing
+    /// Recomputes the total; a payroll that is no longer fully covered goes
+    /// back to `Created`, and any deposit above the new total is refunded.
+    /// Returns the new total amount.
+    pub fn amend_payroll(
+        env: Env,
+        payroll_id: u64,
+        employer: Address,
+        amendments: Vec<PayrollAmendment>,
+    ) -> Result<u64, Error> {
+        employer.require_auth();
+
+        // Check circuit breaker
+        let breaker_active: bool = env.storage().instance().get(&CIRCUIT_BREAKER).unwrap_or(false);
+        if breaker_active {
+            return Err(Error::CircuitBreakerActive);
+        }
 
         let mut payroll: PayrollData = env.storage().persistent()
             .get(&payroll_id)
             .ok_or(Error::PayrollNotFound)?;
 
-        // Verify authorization
-        if caller != payroll.employer {
+        if employer != payroll.employer {
             return Err(Error::NotAuthorized);
         }
 
-        // Check if payroll is funded
-        if payroll.status != PayrollStatus::Funded {
-            return Err(Error::PayrollNotFunded);
+        if payroll.status != PayrollStatus::Created && payroll.status != PayrollStatus::Funded {
+            return Err(Error::PayrollNotAmendable);
         }
 
-        // Check timing for scheduled releases
-        if payroll.schedule_type == ScheduleType::Scheduled {
-            if env.ledger().timestamp() < payroll.release_time {
-                return Err(Error::TooEarly);
+        // Apply amendments in order
+        for amendment in amendments.iter() {
+            match amendment {
+                PayrollAmendment::AddRecipient(address, amount) => {
+                    if find_recipient(&payroll.recipients, &address).is_some() {
+                        return Err(Error::DuplicateRecipient);
+                    }
+                    if amount == 0 {
+                        return Err(Error::ZeroRecipientAmount);
+                    }
+                    if payroll.recipients.len() >= MAX_RECIPIENTS {
+                        return Err(Error::TooManyRecipients);
+                    }
+                    push_index(&env, &DataKey::RecipientPayrolls(address.clone()), payroll_id);
+                    payroll.recipients.push_back(Recipient {
+                        address,
+                        amount,
+                        paid: false,
+                        stream_id: None,
+                    });
+                }
+                PayrollAmendment::RemoveRecipient(address) => {
+                    let index = find_recipient(&payroll.recipients, &address)
+                        .ok_or(Error::RecipientNotFound)?;
+                    if advanced_amount(&env, payroll_id, &address) > 0 {
+                        return Err(Error::AdvanceOutstanding);
+                    }
+                    payroll.recipients.remove(index);
+                    remove_from_index(&env, &DataKey::RecipientPayrolls(address), payroll_id);
+                }
+                PayrollAmendment::SetAmount(address, amount) => {
+                    let index = find_recipient(&payroll.recipients, &address)
+                        .ok_or(Error::RecipientNotFound)?;
+                    if amount == 0 {
+                        return Err(Error::ZeroRecipientAmount);
+                    }
+                    if amount < advanced_amount(&env, payroll_id, &address) {
+                        return Err(Error::AdvanceOutstanding);
+                    }
+                    let mut recipient = payroll.recipients.get_unchecked(index);
+                    recipient.amount = amount;
+                    payroll.recipients.set(index, recipient);
+                }
             }
         }
 
-        // Update status
-        payroll.status = PayrollStatus::Releasing;
-        env.storage().persistent().set(&payroll_id, &payroll);
-
-        // Process payments to recipients
-        for (i, recipient) in payroll.recipients.iter().enumerate() {
-            if !recipient.paid {
-                // In a real implementation, this would transfer tokens
-                // For now, we just mark as paid
-                let mut updated_recipients = payroll.recipients.clone();
-                let mut updated_recipient = updated_recipients.get(i).unwrap().clone();
-                updated_recipient.paid = true;
-                updated_recipients.set(i, updated_recipient);
-                
-                payroll.recipients = updated_recipients;
-
-                // Emit payment event
-                env.events().publish(
-                    ("payment_released",),
-                    (payroll_id, recipient.address.clone(), recipient.amount)
-                );
-            }
+        // Recompute total against the amended list
+        payroll.total_amount = validate_recipients(&env, &payroll.recipients)?;
+
+        // Refund anything deposited above the new total
+        let refund = payroll.deposited_amount.saturating_sub(payroll.total_amount);
+        if refund > 0 {
+            // In a real implementation, this would transfer tokens back
+            payroll.deposited_amount = payroll.total_amount;
+            env.events().publish(
+                ("payroll_refunded",),
+                (payroll_id, employer.clone(), refund)
+            );
         }
 
-        // Mark as completed
-        payroll.status = PayrollStatus::Completed;
+        payroll.status = if payroll.deposited_amount >= payroll.total_amount {
+            PayrollStatus::Funded
+        } else {
+            PayrollStatus::Created
+        };
+
         env.storage().persistent().set(&payroll_id, &payroll);
 
-        // Emit completion event
+        // Emit event
         env.events().publish(
-            ("payroll_completed",),
-            (payroll_id, payroll.total_amount)
+            ("payroll_amended",),
+            (payroll_id, employer, payroll.total_amount, payroll.deposited_amount)
         );
 
-        Ok(())
+        Ok(payroll.total_amount)
     }
 
-    /// Start a streaming payment
+    /// Start a streaming payment in `asset`
     pub fn start_stream(
         env: Env,
         from: Address,
@@ -341,6 +772,7 @@ impl PayrollEscrowContract {
         rate_per_sec: u64,
         duration: u64,
         total_amount: u64,
+        asset: Address,
     ) -> Result<u64, Error> {
         from.require_auth();
 
@@ -348,35 +780,94 @@ impl PayrollEscrowContract {
             return Err(Error::InvalidAmount);
         }
 
-        // Get next stream ID
-        let mut counter: u64 = env.storage().instance().get(&STREAM_COUNTER).unwrap_or(0);
-        counter += 1;
-        env.storage().instance().set(&STREAM_COUNTER, &counter);
+        open_stream(&env, from, to, rate_per_sec, duration, total_amount, None, linear_schedule(), asset)
+    }
 
-        let current_time = env.ledger().timestamp();
-        let stream = StreamData {
-            from: from.clone(),
-            to: to.clone(),
-            rate_per_sec,
-            start_time: current_time,
-            end_time: current_time + duration,
-            last_withdrawal: current_time,
-            total_deposited: total_amount,
-            total_withdrawn: 0,
-            active: true,
-        };
+    /// Start one linear stream per spec for a whole team
+    /// The batch is validated up front and funded with a single combined
+    /// deposit, so either every stream is created or none is. Returns the
+    /// new stream ids in spec order.
+    pub fn start_streams(
+        env: Env,
+        from: Address,
+        asset: Address,
+        specs: Vec<StreamSpec>,
+    ) -> Result<Vec<u64>, Error> {
+        from.require_auth();
 
-        // Store stream
-        let stream_key = format!("STREAM_{}", counter);
-        env.storage().persistent().set(&stream_key, &stream);
+        // Check circuit breaker
+        let breaker_active: bool = env.storage().instance().get(&CIRCUIT_BREAKER).unwrap_or(false);
+        if breaker_active {
+            return Err(Error::CircuitBreakerActive);
+        }
 
-        // Emit event
+        if specs.is_empty() {
+            return Err(Error::InvalidRecipients);
+        }
+        if specs.len() > MAX_STREAM_BATCH {
+            return Err(Error::TooManyRecipients);
+        }
+
+        let mut total_deposit: u64 = 0;
+        for spec in specs.iter() {
+            if spec.rate_per_sec == 0 || spec.duration == 0 || spec.total_amount == 0 {
+                return Err(Error::InvalidAmount);
+            }
+            total_deposit = total_deposit
+                .checked_add(spec.total_amount)
+                .ok_or(Error::InvalidAmount)?;
+        }
+
+        // In a real implementation, this would transfer total_deposit once
         env.events().publish(
-            ("stream_started",),
-            (counter, from, to, rate_per_sec, duration)
+            ("streams_funded",),
+            (from.clone(), asset.clone(), specs.len(), total_deposit)
         );
 
-        Ok(counter)
+        let mut stream_ids = Vec::new(&env);
+        for spec in specs.iter() {
+            let stream_id = open_stream(
+                &env,
+                from.clone(),
+                spec.to,
+                spec.rate_per_sec,
+                spec.duration,
+                spec.total_amount,
+                None,
+                linear_schedule(),
+                asset.clone(),
+            )?;
+            stream_ids.push_back(stream_id);
+        }
+
+        Ok(stream_ids)
+    }
+
+    /// Start a streaming payment with a delayed start, cliff or vesting curve
+    /// Before the cliff nothing is withdrawable; at the cliff everything
+    /// vested since `start_time` unlocks at once. For `Tranches` the stream
+    /// ends at the last unlock and `rate_per_sec` and `duration` are ignored.
+    pub fn start_scheduled_stream(
+        env: Env,
+        from: Address,
+        to: Address,
+        rate_per_sec: u64,
+        duration: u64,
+        total_amount: u64,
+        asset: Address,
+        schedule: StreamSchedule,
+    ) -> Result<u64, Error> {
+        from.require_auth();
+
+        if total_amount == 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let is_tranches = matches!(schedule.curve, VestingCurve::Tranches(_));
+        if !is_tranches && (rate_per_sec == 0 || duration == 0) {
+            return Err(Error::InvalidAmount);
+        }
+
+        open_stream(&env, from, to, rate_per_sec, duration, total_amount, None, schedule, asset)
     }
 
     /// Withdraw from a stream
@@ -386,53 +877,30 @@ impl PayrollEscrowContract {
         to: Address,
     ) -> Result<u64, Error> {
         to.require_auth();
+        withdraw_from_stream(&env, stream_id, to.clone(), None, to)
+    }
 
-        let stream_key = format!("STREAM_{}", stream_id);
-        let mut stream: StreamData = env.storage().persistent()
-            .get(&stream_key)
-            .ok_or(Error::StreamNotFound)?;
+    /// Withdraw part of a stream's accrued balance to any destination
+    /// Only the stream recipient can withdraw; whatever is left stays
+    /// claimable later.
+    pub fn withdraw_stream_amount(
+        env: Env,
+        stream_id: u64,
+        to: Address,
+        amount: u64,
+        destination: Address,
+    ) -> Result<u64, Error> {
+        to.require_auth();
 
-        if !stream.active {
-            return Err(Error::StreamInactive);
+        if amount == 0 {
+            return Err(Error::InvalidAmount);
         }
-
-        if to != stream.to {
-            return Err(Error::NotAuthorized);
-        }
-
-        let current_time = env.ledger().timestamp();
-        let time_elapsed = current_time.saturating_sub(stream.last_withdrawal);
-        let available_amount = time_elapsed * stream.rate_per_sec;
-        
-        // Don't exceed total deposited amount
-        let max_withdrawable = stream.total_deposited.saturating_sub(stream.total_withdrawn);
-        let withdrawal_amount = available_amount.min(max_withdrawable);
-
-        if withdrawal_amount == 0 {
-            return Ok(0);
-        }
-
-        // Update stream
-        stream.total_withdrawn += withdrawal_amount;
-        stream.last_withdrawal = current_time;
-        
-        // Deactivate if fully withdrawn
-        if stream.total_withdrawn >= stream.total_deposited {
-            stream.active = false;
-        }
-
-        env.storage().persistent().set(&stream_key, &stream);
-
-        // Emit event
-        env.events().publish(
-            ("stream_withdrawn",),
-            (stream_id, to, withdrawal_amount)
-        );
-
-        Ok(withdrawal_amount)
-    }
+        withdraw_from_stream(&env, stream_id, to, Some(amount), destination)
+    }
 
     /// Cancel a payroll (only employer)
+    /// A payroll that has started paying out, through a batch, by opening
+    /// its recipients' streams or as an advance, can no longer be cancelled.
     pub fn cancel_payroll(
         env: Env,
         payroll_id: u64,
@@ -451,9 +919,24 @@ impl PayrollEscrowContract {
         if payroll.status == PayrollStatus::Completed {
             return Err(Error::PayrollCompleted);
         }
+        if payroll.status == PayrollStatus::Releasing {
+            return Err(Error::PayrollReleasing);
+        }
+
+        // Wages drawn early already left the escrow and can't be refunded
+        if may_have_advances(&env, &payroll) {
+            for recipient in payroll.recipients.iter() {
+                if advanced_amount(&env, payroll_id, &recipient.address) > 0 {
+                    return Err(Error::AdvanceOutstanding);
+                }
+            }
+        }
 
         payroll.status = PayrollStatus::Cancelled;
         env.storage().persistent().set(&payroll_id, &payroll);
+        for recipient in payroll.recipients.iter() {
+            remove_from_index(&env, &DataKey::RecipientPayrolls(recipient.address), payroll_id);
+        }
 
         // Emit event
         env.events().publish(
@@ -464,75 +947,62 @@ impl PayrollEscrowContract {
         Ok(())
     }
 
-    /// Get payroll status and details
-    pub fn get_payroll_status(
-        env: Env,
-        payroll_id: u64,
-    ) -> Result<PayrollData, Error> {
-        env.storage().persistent()
-            .get(&payroll_id)
-            .ok_or(Error::PayrollNotFound)
-    }
-
-    /// Get stream details
-    pub fn get_stream_status(
-        env: Env,
-        stream_id: u64,
-    ) -> Result<StreamData, Error> {
-        let stream_key = format!("STREAM_{}", stream_id);
-        env.storage().persistent()
-            .get(&stream_key)
-            .ok_or(Error::StreamNotFound)
-    }
-
-    /// Emergency circuit breaker (admin only)
-    pub fn toggle_circuit_breaker(
+    /// Let the employer's workers draw part of their earned wages before
+    /// a scheduled or recurring payroll is released
+    pub fn set_advance_policy(
         env: Env,
-        admin: Address,
-    ) -> Result<bool, Error> {
-        admin.require_auth();
-
-        let stored_admin: Address = env.storage().instance()
-            .get(&ADMIN)
-            .ok_or(Error::NotAuthorized)?;
+        employer: Address,
+        policy: AdvancePolicy,
+    ) -> Result<(), Error> {
+        employer.require_auth();
 
-        if admin != stored_admin {
-            return Err(Error::NotAuthorized);
+        if policy.max_percent as u64 > BASIS_POINTS {
+            return Err(Error::InvalidAmount);
         }
 
-        let current_state: bool = env.storage().instance()
-            .get(&CIRCUIT_BREAKER)
-            .unwrap_or(false);
-        
-        let new_state = !current_state;
-        env.storage().instance().set(&CIRCUIT_BREAKER, &new_state);
+        env.storage().persistent().set(&DataKey::AdvancePolicy(employer.clone()), &policy);
 
+        // Emit event
         env.events().publish(
-            ("circuit_breaker_toggled",),
-            (admin, new_state)
+            ("advance_policy_set",),
+            (employer, policy.max_percent, policy.period_cap)
         );
 
-        Ok(new_state)
+        Ok(())
     }
 
-    // =============================================================================
-    // EMPLOYEE MANAGEMENT FUNCTIONS
-    // =============================================================================
-
-    /// Add a new employee to the system
-    pub fn add_employee(
+    /// Get an employer's advance policy
+    pub fn get_advance_policy(
         env: Env,
         employer: Address,
-        wallet_address: Address,
-        email: String,
-        first_name: String,
-        last_name: String,
-        position: String,
-        salary: u64,
-        currency: String,
-        payment_schedule: String,
+    ) -> Result<AdvancePolicy, Error> {
+        env.storage().persistent()
+            .get(&DataKey::AdvancePolicy(employer))
+            .ok_or(Error::AdvanceNotAvailable)
+    }
+
+    /// Amount `recipient` can currently draw early from a payroll
+    pub fn advance_available(
+        env: Env,
+        payroll_id: u64,
+        recipient: Address,
     ) -> Result<u64, Error> {
-        employer.require_auth();
+        let payroll: PayrollData = env.storage().persistent()
+            .get(&payroll_id)
+            .ok_or(Error::PayrollNotFound)?;
+        advance_limit(&env, payroll_id, &payroll, &recipient)
+    }
+
+    /// Draw wages earned so far this period out of a funded payroll's
+    /// escrow. The total drawn is deducted from the recipient's payment at
+    /// release. Returns the total advanced against this payroll.
+    pub fn draw_advance(
+        env: Env,
+        payroll_id: u64,
+        recipient: Address,
+        amount: u64,
+    ) -> Result<u64, Error> {
+        recipient.require_auth();
 
         // Check circuit breaker
         let breaker_active: bool = env.storage().instance().get(&CIRCUIT_BREAKER).unwrap_or(false);
@@ -540,191 +1010,2368 @@ impl PayrollEscrowContract {
             return Err(Error::CircuitBreakerActive);
         }
 
-        // Get and increment employee counter
-        let mut employee_counter: u64 = env.storage().instance()
-            .get(&EMPLOYEE_COUNTER)
-            .unwrap_or(0);
-        employee_counter += 1;
+        if amount == 0 {
+            return Err(Error::InvalidAmount);
+        }
 
-        let current_time = env.ledger().timestamp();
+        let payroll: PayrollData = env.storage().persistent()
+            .get(&payroll_id)
+            .ok_or(Error::PayrollNotFound)?;
+        if amount > advance_limit(&env, payroll_id, &payroll, &recipient)? {
+            return Err(Error::InsufficientBalance);
+        }
 
-        // Create employee data
-        let employee = Employee {
-            id: employee_counter,
-            employer: employer.clone(),
-            wallet_address: wallet_address.clone(),
-            email: email.clone(),
-            first_name: first_name.clone(),
-            last_name: last_name.clone(),
-            position: position.clone(),
-            salary,
-            currency: currency.clone(),
-            payment_schedule: payment_schedule.clone(),
-            status: EmployeeStatus::Active,
-            created_at: current_time,
-            updated_at: current_time,
-        };
+        let advanced = advanced_amount(&env, payroll_id, &recipient) + amount;
+        env.storage().persistent().set(&DataKey::Advance(payroll_id, recipient.clone()), &advanced);
 
-        // Save employee data
-        let employee_key = format!("EMPLOYEE_{}", employee_counter);
-        env.storage().persistent().set(&employee_key, &employee);
+        // In a real implementation, this would transfer tokens
+        env.events().publish(
+            ("advance_drawn",),
+            (payroll_id, recipient.clone(), amount)
+        );
+        pay_employee_splits(&env, &payroll.employer, &recipient, amount, false);
 
-        // Save employer's employee list
-        let employer_key = format!("EMPLOYER_EMPLOYEES_{}", employer);
-        let mut employee_list: Vec<u64> = env.storage().persistent()
-            .get(&employer_key)
-            .unwrap_or(Vec::new(&env));
-        employee_list.push_back(employee_counter);
-        env.storage().persistent().set(&employer_key, &employee_list);
+        Ok(advanced)
+    }
 
-        // Update counter
-        env.storage().instance().set(&EMPLOYEE_COUNTER, &employee_counter);
+    /// Set the deductions withheld from every payment the employer releases
+    /// Rules apply in order: a percentage is taken from the gross payment
+    /// and a fixed amount is capped at whatever is left. An empty list
+    /// removes all deductions. Streamed pay is not subject to deductions.
+    pub fn set_deduction_rules(
+        env: Env,
+        employer: Address,
+        rules: Vec<DeductionRule>,
+    ) -> Result<(), Error> {
+        employer.require_auth();
+
+        if rules.len() > MAX_DEDUCTION_RULES {
+            return Err(Error::InvalidDeduction);
+        }
+
+        let mut total_percent: u64 = 0;
+        for rule in rules.iter() {
+            match rule.share {
+                SplitShare::Percent(0) | SplitShare::Fixed(0) => return Err(Error::InvalidDeduction),
+                SplitShare::Percent(percent) => total_percent += percent as u64,
+                SplitShare::Fixed(_) => {}
+            }
+        }
+        if total_percent > BASIS_POINTS {
+            return Err(Error::InvalidDeduction);
+        }
+
+        let key = DataKey::DeductionRules(employer.clone());
+        if rules.is_empty() {
+            env.storage().persistent().remove(&key);
+        } else {
+            env.storage().persistent().set(&key, &rules);
+        }
 
         // Emit event
         env.events().publish(
-            ("employee_added",),
-            (employee_counter, employer, wallet_address, email, salary)
+            ("deduction_rules_set",),
+            (employer, rules.len())
         );
 
-        Ok(employee_counter)
+        Ok(())
     }
 
-    /// Get employee details by ID
-    pub fn get_employee(
+    /// Get an employer's deduction rules
+    pub fn get_deduction_rules(
         env: Env,
-        employee_id: u64,
-    ) -> Result<Employee, Error> {
-        let employee_key = format!("EMPLOYEE_{}", employee_id);
+        employer: Address,
+    ) -> Vec<DeductionRule> {
         env.storage().persistent()
-            .get(&employee_key)
-            .ok_or(Error::PayrollNotFound) // Reusing error for not found
+            .get(&DataKey::DeductionRules(employer))
+            .unwrap_or(Vec::new(&env))
     }
 
-    /// Get all employees for an employer
-    pub fn get_employer_employees(
+    /// Get the payslip recorded when a recipient's payment was released
+    /// Only payments with deductions or an advance have a payslip.
+    pub fn get_payslip(
+        env: Env,
+        payroll_id: u64,
+        recipient: Address,
+    ) -> Result<Payslip, Error> {
+        env.storage().persistent()
+            .get(&DataKey::Payslip(payroll_id, recipient))
+            .ok_or(Error::RecipientNotFound)
+    }
+
+    /// Get payroll status and details
+    pub fn get_payroll_status(
+        env: Env,
+        payroll_id: u64,
+    ) -> Result<PayrollData, Error> {
+        env.storage().persistent()
+            .get(&payroll_id)
+            .ok_or(Error::PayrollNotFound)
+    }
+
+    /// Payroll ids created by `employer`, oldest first, `limit` at a time
+    pub fn get_employer_payrolls(
         env: Env,
         employer: Address,
-    ) -> Result<Vec<Employee>, Error> {
-        let employer_key = format!("EMPLOYER_EMPLOYEES_{}", employer);
-        let employee_ids: Vec<u64> = env.storage().persistent()
-            .get(&employer_key)
-            .unwrap_or(Vec::new(&env));
+        start: u32,
+        limit: u32,
+    ) -> Vec<u64> {
+        index_page(&env, &DataKey::EmployerPayrolls(employer), start, limit)
+    }
 
-        let mut employees = Vec::new(&env);
-        for employee_id in employee_ids.iter() {
-            let employee_key = format!("EMPLOYEE_{}", employee_id);
-            if let Some(employee) = env.storage().persistent().get::<String, Employee>(&employee_key) {
-                employees.push_back(employee);
-            }
-        }
+    /// Payroll ids that still owe `recipient` a payment, oldest first,
+    /// `limit` at a time. A payroll leaves the list once it pays the
+    /// recipient or is cancelled.
+    pub fn get_recipient_payrolls(
+        env: Env,
+        recipient: Address,
+        start: u32,
+        limit: u32,
+    ) -> Vec<u64> {
+        index_page(&env, &DataKey::RecipientPayrolls(recipient), start, limit)
+    }
 
-        Ok(employees)
+    /// Get stream details
+    pub fn get_stream_status(
+        env: Env,
+        stream_id: u64,
+    ) -> Result<StreamData, Error> {
+        let stream_key = DataKey::Stream(stream_id);
+        env.storage().persistent()
+            .get(&stream_key)
+            .ok_or(Error::StreamNotFound)
     }
 
-    /// Update employee details
-    pub fn update_employee(
+    /// Add funds to a linear stream, extending `end_time` at the same rate
+    /// A stream its recipient has drained is reactivated; payroll streams
+    /// settle their payroll once drained, so those stay closed.
+    pub fn top_up_stream(
         env: Env,
-        employer: Address,
-        employee_id: u64,
-        salary: Option<u64>,
-        position: Option<String>,
-        payment_schedule: Option<String>,
-        status: Option<EmployeeStatus>,
-    ) -> Result<(), Error> {
-        employer.require_auth();
+        stream_id: u64,
+        from: Address,
+        amount: u64,
+    ) -> Result<StreamData, Error> {
+        from.require_auth();
 
-        let employee_key = format!("EMPLOYEE_{}", employee_id);
-        let mut employee: Employee = env.storage().persistent()
-            .get(&employee_key)
-            .ok_or(Error::PayrollNotFound)?;
+        if amount == 0 {
+            return Err(Error::InvalidAmount);
+        }
 
-        // Verify employer owns this employee
-        if employee.employer != employer {
+        let stream_key = DataKey::Stream(stream_id);
+        let mut stream: StreamData = env.storage().persistent()
+            .get(&stream_key)
+            .ok_or(Error::StreamNotFound)?;
+
+        if stream.from != from {
             return Err(Error::NotAuthorized);
         }
 
-        // Update fields if provided
-        if let Some(new_salary) = salary {
-            employee.salary = new_salary;
-        }
-        if let Some(new_position) = position {
-            employee.position = new_position;
+        let reactivated = !stream.active;
+        if reactivated && stream.payroll_id.is_some() {
+            return Err(Error::StreamInactive);
         }
-        if let Some(new_schedule) = payment_schedule {
-            employee.payment_schedule = new_schedule;
+
+        // Funds added after the stream fully accrued start flowing from now
+        settle_stream_accrual(&mut stream, env.ledger().timestamp())?;
+        stream.total_deposited = stream.total_deposited
+            .checked_add(amount)
+            .ok_or(Error::InvalidAmount)?;
+        stream.end_time = linear_end_time(&stream);
+        stream.active = true;
+
+        env.storage().persistent().set(&stream_key, &stream);
+        if reactivated {
+            push_index(&env, &DataKey::RecipientStreams(stream.to.clone()), stream_id);
         }
-        if let Some(new_status) = status {
-            employee.status = new_status;
+
+        // Emit event
+        env.events().publish(
+            ("stream_topped_up",),
+            (stream_id, from, amount, stream.end_time)
+        );
+
+        Ok(stream)
+    }
+
+    /// Change the rate of a linear stream
+    /// Funds accrued so far are settled at the old rate first; the rest of
+    /// the deposit flows at the new rate and `end_time` moves accordingly.
+    pub fn change_stream_rate(
+        env: Env,
+        stream_id: u64,
+        from: Address,
+        new_rate_per_sec: u64,
+    ) -> Result<StreamData, Error> {
+        from.require_auth();
+
+        if new_rate_per_sec == 0 {
+            return Err(Error::InvalidAmount);
         }
 
-        employee.updated_at = env.ledger().timestamp();
+        let stream_key = DataKey::Stream(stream_id);
+        let mut stream = load_sender_stream(&env, &stream_key, &from)?;
 
-        // Save updated employee
-        env.storage().persistent().set(&employee_key, &employee);
+        let old_rate = stream.rate_per_sec;
+        settle_stream_accrual(&mut stream, env.ledger().timestamp())?;
+        stream.rate_per_sec = new_rate_per_sec;
+        stream.end_time = linear_end_time(&stream);
+
+        env.storage().persistent().set(&stream_key, &stream);
 
         // Emit event
         env.events().publish(
-            ("employee_updated",),
-            (employee_id, employer, employee.salary)
+            ("stream_rate_changed",),
+            (stream_id, from, old_rate, new_rate_per_sec, stream.settled_amount)
         );
 
-        Ok(())
+        Ok(stream)
     }
 
-    /// Remove/terminate an employee
-    pub fn remove_employee(
+    /// Pause a stream (sender only)
+    /// Nothing accrues while paused; the recipient can still withdraw what
+    /// accrued before the pause.
+    pub fn pause_stream(
         env: Env,
-        employer: Address,
-        employee_id: u64,
+        stream_id: u64,
+        from: Address,
     ) -> Result<(), Error> {
-        employer.require_auth();
+        from.require_auth();
 
-        let employee_key = format!("EMPLOYEE_{}", employee_id);
-        let mut employee: Employee = env.storage().persistent()
-            .get(&employee_key)
-            .ok_or(Error::PayrollNotFound)?;
+        let stream_key = DataKey::Stream(stream_id);
+        let mut stream = load_sender_stream(&env, &stream_key, &from)?;
 
-        // Verify employer owns this employee
-        if employee.employer != employer {
-            return Err(Error::NotAuthorized);
+        if stream.paused {
+            return Err(Error::StreamPaused);
         }
 
-        // Mark as terminated instead of deleting
-        employee.status = EmployeeStatus::Terminated;
-        employee.updated_at = env.ledger().timestamp();
-
-        env.storage().persistent().set(&employee_key, &employee);
+        stream.paused = true;
+        stream.paused_at = env.ledger().timestamp();
+        env.storage().persistent().set(&stream_key, &stream);
 
         // Emit event
         env.events().publish(
-            ("employee_terminated",),
-            (employee_id, employer)
+            ("stream_paused",),
+            (stream_id, from, stream.paused_at)
         );
 
         Ok(())
     }
 
-    /// Get employee count for an employer
-    pub fn get_employee_count(
+    /// Resume a paused stream (sender only)
+    /// Every part of the schedule not yet reached when the stream was
+    /// paused, including `end_time`, moves back by the paused duration.
+    pub fn resume_stream(
         env: Env,
-        employer: Address,
-    ) -> u64 {
-        let employer_key = format!("EMPLOYER_EMPLOYEES_{}", employer);
-        let employee_ids: Vec<u64> = env.storage().persistent()
-            .get(&employer_key)
-            .unwrap_or(Vec::new(&env));
-
-        // Count only active employees
-        let mut count = 0u64;
+        stream_id: u64,
+        from: Address,
+    ) -> Result<StreamData, Error> {
+        from.require_auth();
+
+        let stream_key = DataKey::Stream(stream_id);
+        let mut stream = load_sender_stream(&env, &stream_key, &from)?;
+
+        if !stream.paused {
+            return Err(Error::StreamNotPaused);
+        }
+
+        let paused_at = stream.paused_at;
+        let paused_for = env.ledger().timestamp().saturating_sub(paused_at);
+        let shift = |time: u64| if time > paused_at { time + paused_for } else { time };
+
+        stream.start_time += paused_for;
+        stream.cliff_time = shift(stream.cliff_time);
+        stream.end_time = shift(stream.end_time);
+        if let VestingCurve::Tranches(tranches) = &stream.curve {
+            let mut shifted = Vec::new(&env);
+            for tranche in tranches.iter() {
+                shifted.push_back(Tranche {
+                    unlock_time: shift(tranche.unlock_time),
+                    amount: tranche.amount,
+                });
+            }
+            stream.curve = VestingCurve::Tranches(shifted);
+        }
+        stream.paused = false;
+        stream.paused_at = 0;
+
+        env.storage().persistent().set(&stream_key, &stream);
+
+        // Emit event
+        env.events().publish(
+            ("stream_resumed",),
+            (stream_id, from, paused_for)
+        );
+
+        Ok(stream)
+    }
+
+    /// Hand the right to receive a stream to a new address
+    /// Funds accrued so far are paid to the current recipient first.
+    pub fn transfer_stream(
+        env: Env,
+        stream_id: u64,
+        to: Address,
+        new_recipient: Address,
+    ) -> Result<(), Error> {
+        to.require_auth();
+
+        let stream_key = DataKey::Stream(stream_id);
+        let stream: StreamData = env.storage().persistent()
+            .get(&stream_key)
+            .ok_or(Error::StreamNotFound)?;
+
+        if to != stream.to {
+            return Err(Error::NotAuthorized);
+        }
+
+        if !stream.active {
+            return Err(Error::StreamInactive);
+        }
+
+        if !stream.transferable {
+            return Err(Error::StreamNotTransferable);
+        }
+
+        if new_recipient == to {
+            return Err(Error::InvalidRecipients);
+        }
+
+        // Settle what the current recipient has earned
+        let settled = withdraw_from_stream(&env, stream_id, to.clone(), None, to.clone())?;
+
+        let mut stream: StreamData = env.storage().persistent()
+            .get(&stream_key)
+            .ok_or(Error::StreamNotFound)?;
+        stream.to = new_recipient.clone();
+        env.storage().persistent().set(&stream_key, &stream);
+
+        // Move the stream between the recipients' indexes
+        remove_from_index(&env, &DataKey::RecipientStreams(to.clone()), stream_id);
+        push_index(&env, &DataKey::RecipientStreams(new_recipient.clone()), stream_id);
+
+        // Emit event
+        env.events().publish(
+            ("stream_transferred",),
+            (stream_id, to, new_recipient, settled)
+        );
+
+        Ok(())
+    }
+
+    /// Allow or forbid the recipient from transferring a stream (sender only)
+    pub fn set_stream_transferable(
+        env: Env,
+        stream_id: u64,
+        from: Address,
+        transferable: bool,
+    ) -> Result<(), Error> {
+        from.require_auth();
+
+        let stream_key = DataKey::Stream(stream_id);
+        let mut stream = load_sender_stream(&env, &stream_key, &from)?;
+
+        stream.transferable = transferable;
+        env.storage().persistent().set(&stream_key, &stream);
+
+        // Emit event
+        env.events().publish(
+            ("stream_transferable_set",),
+            (stream_id, from, transferable)
+        );
+
+        Ok(())
+    }
+
+    /// Ids of the active streams paying `recipient`, oldest first
+    pub fn get_recipient_streams(
+        env: Env,
+        recipient: Address,
+    ) -> Vec<u64> {
+        env.storage().persistent()
+            .get(&DataKey::RecipientStreams(recipient))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Withdraw everything accrued in `asset` across all of the caller's
+    /// active streams. Returns the total withdrawn.
+    pub fn withdraw_all(
+        env: Env,
+        to: Address,
+        asset: Address,
+    ) -> Result<u64, Error> {
+        to.require_auth();
+
+        let stream_ids: Vec<u64> = env.storage().persistent()
+            .get(&DataKey::RecipientStreams(to.clone()))
+            .unwrap_or(Vec::new(&env));
+
+        let current_time = env.ledger().timestamp();
+        let mut total_withdrawn: u64 = 0;
+        for stream_id in stream_ids.iter() {
+            let stream: StreamData = match env.storage().persistent().get(&DataKey::Stream(stream_id)) {
+                Some(stream) => stream,
+                None => continue,
+            };
+            if stream.asset != asset || stream_balance_at(&stream, current_time).withdrawable == 0 {
+                continue;
+            }
+
+            total_withdrawn += withdraw_from_stream(&env, stream_id, to.clone(), None, to.clone())?;
+        }
+
+        Ok(total_withdrawn)
+    }
+
+    /// Amount `withdraw_stream` would pay out at the current ledger time
+    pub fn stream_withdrawable(
+        env: Env,
+        stream_id: u64,
+    ) -> Result<u64, Error> {
+        let stream_key = DataKey::Stream(stream_id);
+        let stream: StreamData = env.storage().persistent()
+            .get(&stream_key)
+            .ok_or(Error::StreamNotFound)?;
+
+        Ok(stream_balance_at(&stream, env.ledger().timestamp()).withdrawable)
+    }
+
+    /// Accrued, withdrawn and remaining amounts of a stream at the current
+    /// ledger time, without modifying it
+    pub fn stream_balance(
+        env: Env,
+        stream_id: u64,
+    ) -> Result<StreamBalance, Error> {
+        let stream_key = DataKey::Stream(stream_id);
+        let stream: StreamData = env.storage().persistent()
+            .get(&stream_key)
+            .ok_or(Error::StreamNotFound)?;
+
+        Ok(stream_balance_at(&stream, env.ledger().timestamp()))
+    }
+
+    /// Emergency circuit breaker (admin only)
+    pub fn toggle_circuit_breaker(
+        env: Env,
+        admin: Address,
+    ) -> Result<bool, Error> {
+        admin.require_auth();
+
+        let stored_admin: Address = env.storage().instance()
+            .get(&ADMIN)
+            .ok_or(Error::NotAuthorized)?;
+
+        if admin != stored_admin {
+            return Err(Error::NotAuthorized);
+        }
+
+        let current_state: bool = env.storage().instance()
+            .get(&CIRCUIT_BREAKER)
+            .unwrap_or(false);
+        
+        let new_state = !current_state;
+        env.storage().instance().set(&CIRCUIT_BREAKER, &new_state);
+
+        env.events().publish(
+            ("circuit_breaker_toggled",),
+            (admin, new_state)
+        );
+
+        Ok(new_state)
+    }
+
+    // =============================================================================
+    // EMPLOYEE MANAGEMENT FUNCTIONS
+    // =============================================================================
+
+    /// Map one of the employer's currency codes to an asset contract,
+    /// replacing any existing mapping for that code
+    pub fn set_currency_asset(
+        env: Env,
+        employer: Address,
+        currency: String,
+        asset: Address,
+    ) {
+        employer.require_auth();
+
+        let key = DataKey::CurrencyAsset(employer.clone(), currency.clone());
+        env.storage().persistent().set(&key, &asset);
+
+        // Emit event
+        env.events().publish(
+            ("currency_asset_set",),
+            (employer, currency, asset)
+        );
+    }
+
+    /// Get the asset contract an employer pays a currency in
+    pub fn get_currency_asset(
+        env: Env,
+        employer: Address,
+        currency: String,
+    ) -> Result<Address, Error> {
+        currency_asset(&env, &employer, &currency)
+    }
+
+    /// Add a new employee to the system
+    pub fn add_employee(
+        env: Env,
+        employer: Address,
+        wallet_address: Address,
+        email: String,
+        first_name: String,
+        last_name: String,
+        position: String,
+        salary: u64,
+        currency: String,
+        payment_schedule: String,
+    ) -> Result<u64, Error> {
+        employer.require_auth();
+
+        // Check circuit breaker
+        let breaker_active: bool = env.storage().instance().get(&CIRCUIT_BREAKER).unwrap_or(false);
+        if breaker_active {
+            return Err(Error::CircuitBreakerActive);
+        }
+
+        let new_employee = NewEmployee {
+            wallet_address,
+            email,
+            first_name,
+            last_name,
+            position,
+            salary,
+            currency,
+            payment_schedule,
+        };
+
+        // One record per wallet and per email for each employer
+        let email = email_hash(&env, &new_employee.email)?;
+        if employee_exists(&env, &employer, &new_employee.wallet_address, &email) {
+            return Err(Error::DuplicateEmployee);
+        }
+        currency_asset(&env, &employer, &new_employee.currency)?;
+
+        // Get and increment employee counter
+        let employee_counter: u64 = env.storage().instance()
+            .get(&EMPLOYEE_COUNTER)
+            .unwrap_or(0u64) + 1;
+
+        store_new_employee(&env, &employer, employee_counter, new_employee, email);
+        push_index(&env, &DataKey::EmployerEmployees(employer), employee_counter);
+        env.storage().instance().set(&EMPLOYEE_COUNTER, &employee_counter);
+
+        Ok(employee_counter)
+    }
+
+    /// Add many employees at once
+    /// Every record is checked, against existing employees and the rest of
+    /// the batch, before any is created, so either all are added or none
+    /// is. Returns the new employee ids in input order.
+    pub fn add_employees(
+        env: Env,
+        employer: Address,
+        employees: Vec<NewEmployee>,
+    ) -> Result<Vec<u64>, Error> {
+        employer.require_auth();
+
+        // Check circuit breaker
+        let breaker_active: bool = env.storage().instance().get(&CIRCUIT_BREAKER).unwrap_or(false);
+        if breaker_active {
+            return Err(Error::CircuitBreakerActive);
+        }
+
+        if employees.is_empty() {
+            return Err(Error::InvalidRecipients);
+        }
+        if employees.len() > MAX_EMPLOYEE_BATCH {
+            return Err(Error::TooManyRecipients);
+        }
+
+        let mut wallets: Map<Address, ()> = Map::new(&env);
+        let mut emails: Map<BytesN<32>, ()> = Map::new(&env);
+        let mut email_hashes: Vec<BytesN<32>> = Vec::new(&env);
+        for new_employee in employees.iter() {
+            let email = email_hash(&env, &new_employee.email)?;
+            if employee_exists(&env, &employer, &new_employee.wallet_address, &email)
+                || wallets.contains_key(new_employee.wallet_address.clone())
+                || emails.contains_key(email.clone())
+            {
+                return Err(Error::DuplicateEmployee);
+            }
+            currency_asset(&env, &employer, &new_employee.currency)?;
+            wallets.set(new_employee.wallet_address, ());
+            emails.set(email.clone(), ());
+            email_hashes.push_back(email);
+        }
+
+        let mut employee_counter: u64 = env.storage().instance()
+            .get(&EMPLOYEE_COUNTER)
+            .unwrap_or(0);
+        let employer_key = DataKey::EmployerEmployees(employer.clone());
+        let mut employee_list: Vec<u64> = env.storage().persistent()
+            .get(&employer_key)
+            .unwrap_or(Vec::new(&env));
+
+        let mut employee_ids = Vec::new(&env);
+        for (new_employee, email) in employees.iter().zip(email_hashes.iter()) {
+            employee_counter += 1;
+            store_new_employee(&env, &employer, employee_counter, new_employee, email);
+            employee_list.push_back(employee_counter);
+            employee_ids.push_back(employee_counter);
+        }
+
+        env.storage().persistent().set(&employer_key, &employee_list);
+        env.storage().instance().set(&EMPLOYEE_COUNTER, &employee_counter);
+
+        Ok(employee_ids)
+    }
+
+    /// Get employee details by ID
+    pub fn get_employee(
+        env: Env,
+        employee_id: u64,
+    ) -> Result<Employee, Error> {
+        load_employee(&env, employee_id)
+    }
+
+    /// Look up an employer's employee by wallet address
+    pub fn find_employee_by_wallet(
+        env: Env,
+        employer: Address,
+        wallet: Address,
+    ) -> Result<Employee, Error> {
+        let employee_id: u64 = env.storage().persistent()
+            .get(&DataKey::EmployeeByWallet(employer, wallet))
+            .ok_or(Error::PayrollNotFound)?; // Reusing error for not found
+        load_employee(&env, employee_id)
+    }
+
+    /// Every employee record paid to `wallet`, across employers, with the
+    /// outstanding payroll claims and active streams each employer owes it
+    pub fn get_my_employments(
+        env: Env,
+        wallet: Address,
+    ) -> Vec<Employment> {
+        let employee_ids: Vec<u64> = env.storage().persistent()
+            .get(&DataKey::WalletEmployees(wallet.clone()))
+            .unwrap_or(Vec::new(&env));
+        let payroll_ids: Vec<u64> = env.storage().persistent()
+            .get(&DataKey::RecipientPayrolls(wallet.clone()))
+            .unwrap_or(Vec::new(&env));
+        let stream_ids: Vec<u64> = env.storage().persistent()
+            .get(&DataKey::RecipientStreams(wallet.clone()))
+            .unwrap_or(Vec::new(&env));
+        let current_time = env.ledger().timestamp();
+
+        // Read each payroll and stream once, then hand them out by employer
+        let mut payroll_claims: Map<Address, Vec<PayrollClaim>> = Map::new(&env);
+        for payroll_id in payroll_ids.iter() {
+            let payroll: PayrollData = match env.storage().persistent().get(&payroll_id) {
+                Some(payroll) => payroll,
+                None => continue,
+            };
+            if payroll.status == PayrollStatus::Completed || payroll.status == PayrollStatus::Cancelled {
+                continue;
+            }
+            let recipient = match find_recipient(&payroll.recipients, &wallet) {
+                Some(index) => payroll.recipients.get(index).unwrap(),
+                None => continue,
+            };
+            // Streamed shares show up under the worker's streams instead
+            if recipient.paid || recipient.stream_id.is_some() {
+                continue;
+            }
+            let mut claims = payroll_claims.get(payroll.employer.clone()).unwrap_or(Vec::new(&env));
+            claims.push_back(PayrollClaim {
+                payroll_id,
+                amount: recipient.amount,
+                asset: payroll.asset,
+                status: payroll.status,
+                release_time: payroll.release_time,
+            });
+            payroll_claims.set(payroll.employer, claims);
+        }
+
+        let mut streams: Map<Address, Vec<StreamClaim>> = Map::new(&env);
+        for stream_id in stream_ids.iter() {
+            let stream: StreamData = match env.storage().persistent().get(&DataKey::Stream(stream_id)) {
+                Some(stream) => stream,
+                None => continue,
+            };
+            if !stream.active {
+                continue;
+            }
+            let mut claims = streams.get(stream.from.clone()).unwrap_or(Vec::new(&env));
+            claims.push_back(StreamClaim {
+                stream_id,
+                asset: stream.asset.clone(),
+                balance: stream_balance_at(&stream, current_time),
+            });
+            streams.set(stream.from, claims);
+        }
+
+        let mut employments = Vec::new(&env);
         for employee_id in employee_ids.iter() {
-            let employee_key = format!("EMPLOYEE_{}", employee_id);
-            if let Some(employee) = env.storage().persistent().get::<String, Employee>(&employee_key) {
-                if employee.status == EmployeeStatus::Active {
-                    count += 1;
+            if let Ok(employee) = load_employee(&env, employee_id) {
+                let employer = employee.employer.clone();
+                employments.push_back(Employment {
+                    employee,
+                    payroll_claims: payroll_claims.get(employer.clone()).unwrap_or(Vec::new(&env)),
+                    streams: streams.get(employer).unwrap_or(Vec::new(&env)),
+                });
+            }
+        }
+        employments
+    }
+
+    /// Get an employer's employees a page at a time
+    /// Reads at most `limit` employee records starting at `cursor`; when
+    /// filtering by status a page can hold fewer than `limit` employees.
+    pub fn get_employer_employees(
+        env: Env,
+        employer: Address,
+        cursor: u32,
+        limit: u32,
+        status_filter: Option<EmployeeStatus>,
+    ) -> Result<EmployeePage, Error> {
+        let employer_key = DataKey::EmployerEmployees(employer.clone());
+        let employee_ids: Vec<u64> = env.storage().persistent()
+            .get(&employer_key)
+            .unwrap_or(Vec::new(&env));
+
+        let start = cursor.min(employee_ids.len());
+        let end = start.saturating_add(limit).min(employee_ids.len());
+
+        let mut employees = Vec::new(&env);
+        for employee_id in employee_ids.slice(start..end).iter() {
+            if let Ok(employee) = load_employee(&env, employee_id) {
+                if status_filter.as_ref().is_none_or(|status| employee.status == *status) {
+                    employees.push_back(employee);
+                }
+            }
+        }
+
+        let next_cursor = if end < employee_ids.len() { Some(end) } else { None };
+        Ok(EmployeePage { employees, next_cursor })
+    }
+
+    /// Update employee details
+    pub fn update_employee(
+        env: Env,
+        employer: Address,
+        employee_id: u64,
+        salary: Option<u64>,
+        position: Option<String>,
+        payment_schedule: Option<String>,
+        status: Option<EmployeeStatus>,
+    ) -> Result<(), Error> {
+        employer.require_auth();
+
+        let employee_key = DataKey::Employee(employee_id);
+        let mut employee = load_employee(&env, employee_id)?;
+
+        // Verify employer owns this employee
+        if employee.employer != employer {
+            return Err(Error::NotAuthorized);
+        }
+
+        // Update fields if provided, recording each change
+        let current_time = env.ledger().timestamp();
+        if let Some(new_salary) = salary {
+            // An immediate change supersedes any scheduled one
+            cancel_pending_salary(&env, employee_id, &employer);
+            if new_salary != employee.salary {
+                let change = EmployeeChange::Salary(employee.salary, new_salary);
+                record_employee_change(&env, employee_id, change, current_time, &employer);
+                employee.salary = new_salary;
+            }
+        }
+        if let Some(new_position) = position {
+            if new_position != employee.position {
+                let change = EmployeeChange::Position(employee.position.clone(), new_position.clone());
+                record_employee_change(&env, employee_id, change, current_time, &employer);
+                employee.position = new_position;
+            }
+        }
+        if let Some(new_schedule) = payment_schedule {
+            if new_schedule != employee.payment_schedule {
+                let change = EmployeeChange::PaymentSchedule(employee.payment_schedule.clone(), new_schedule.clone());
+                record_employee_change(&env, employee_id, change, current_time, &employer);
+                employee.payment_schedule = new_schedule;
+            }
+        }
+        if let Some(new_status) = status {
+            check_status_transition(&employee.status, &new_status)?;
+            if new_status != employee.status {
+                let change = EmployeeChange::Status(employee.status.clone(), new_status.clone());
+                record_employee_change(&env, employee_id, change, current_time, &employer);
+                move_employee_count(&env, &employer, Some(employee.status.clone()), new_status.clone());
+                employee.status = new_status;
+            }
+        }
+
+        employee.updated_at = current_time;
+
+        // Save updated employee
+        env.storage().persistent().set(&employee_key, &employee);
+
+        // Emit event
+        env.events().publish(
+            ("employee_updated",),
+            (employee_id, employer, employee.salary)
+        );
+
+        Ok(())
+    }
+
+    /// Schedule a salary change that takes effect at `effective_at`
+    /// The change is recorded in the employee's history straight away and
+    /// replaces any change already scheduled.
+    pub fn schedule_salary_change(
+        env: Env,
+        employer: Address,
+        employee_id: u64,
+        new_salary: u64,
+        effective_at: u64,
+    ) -> Result<(), Error> {
+        employer.require_auth();
+
+        let employee = load_employee(&env, employee_id)?;
+
+        // Verify employer owns this employee
+        if employee.employer != employer {
+            return Err(Error::NotAuthorized);
+        }
+
+        if effective_at <= env.ledger().timestamp() {
+            return Err(Error::InvalidSchedule);
+        }
+
+        // Keep a change that already took effect before replacing the schedule
+        env.storage().persistent().set(&DataKey::Employee(employee_id), &employee);
+        cancel_pending_salary(&env, employee_id, &employer);
+        let pending = PendingSalaryChange {
+            salary: new_salary,
+            effective_at,
+        };
+        env.storage().persistent().set(&DataKey::PendingSalary(employee_id), &pending);
+
+        let change = EmployeeChange::Salary(employee.salary, new_salary);
+        record_employee_change(&env, employee_id, change, effective_at, &employer);
+
+        // Emit event
+        env.events().publish(
+            ("salary_change_scheduled",),
+            (employee_id, employer, new_salary, effective_at)
+        );
+
+        Ok(())
+    }
+
+    /// Propose a new wallet for an employee (employer only)
+    /// Nothing changes until the new wallet confirms with
+    /// `confirm_wallet_change`; a new request replaces any pending one.
+    pub fn request_wallet_change(
+        env: Env,
+        employer: Address,
+        employee_id: u64,
+        new_wallet: Address,
+    ) -> Result<(), Error> {
+        employer.require_auth();
+
+        let employee = load_employee(&env, employee_id)?;
+
+        // Verify employer owns this employee
+        if employee.employer != employer {
+            return Err(Error::NotAuthorized);
+        }
+
+        if employee.status == EmployeeStatus::Terminated {
+            return Err(Error::InvalidStatusTransition);
+        }
+
+        if new_wallet == employee.wallet_address {
+            return Err(Error::InvalidRecipients);
+        }
+
+        if env.storage().persistent().has(&DataKey::EmployeeByWallet(employer.clone(), new_wallet.clone())) {
+            return Err(Error::DuplicateEmployee);
+        }
+
+        env.storage().persistent().set(&DataKey::PendingWallet(employee_id), &new_wallet);
+
+        // Emit event
+        env.events().publish(
+            ("wallet_change_requested",),
+            (employee_id, employer, new_wallet)
+        );
+
+        Ok(())
+    }
+
+    /// Accept a wallet change requested by the employer, signed by the new wallet
+    pub fn confirm_wallet_change(
+        env: Env,
+        employee_id: u64,
+        new_wallet: Address,
+    ) -> Result<(), Error> {
+        new_wallet.require_auth();
+
+        let pending_key = DataKey::PendingWallet(employee_id);
+        let pending: Address = env.storage().persistent()
+            .get(&pending_key)
+            .ok_or(Error::NotAuthorized)?;
+        if pending != new_wallet {
+            return Err(Error::NotAuthorized);
+        }
+
+        let mut employee = load_employee(&env, employee_id)?;
+        if employee.status == EmployeeStatus::Terminated {
+            return Err(Error::InvalidStatusTransition);
+        }
+
+        env.storage().persistent().remove(&pending_key);
+        apply_wallet_change(&env, &mut employee, &new_wallet, true)?;
+
+        Ok(())
+    }
+
+    /// Move an employee's pay to a new wallet, signed by the current wallet
+    /// Streams the employer made non-transferable keep paying the old
+    /// wallet; moving those needs the employer's `request_wallet_change`.
+    pub fn change_wallet(
+        env: Env,
+        employee_id: u64,
+        new_wallet: Address,
+    ) -> Result<(), Error> {
+        let mut employee = load_employee(&env, employee_id)?;
+        employee.wallet_address.require_auth();
+
+        if employee.status == EmployeeStatus::Terminated {
+            return Err(Error::InvalidStatusTransition);
+        }
+
+        if new_wallet == employee.wallet_address {
+            return Err(Error::InvalidRecipients);
+        }
+
+        // Supersedes anything the employer proposed
+        env.storage().persistent().remove(&DataKey::PendingWallet(employee_id));
+        apply_wallet_change(&env, &mut employee, &new_wallet, false)?;
+
+        Ok(())
+    }
+
+    /// Set how an employee's pay is split (signed by the employee's wallet)
+    /// Splits are applied in order: a percentage is taken from the gross
+    /// payment, a fixed amount is capped at whatever is left, and the
+    /// remainder, including rounding, goes to the employee's wallet. Fixed
+    /// amounts are taken once per pay period, from payroll releases only;
+    /// stream withdrawals and advances only honour percentages. An empty
+    /// list pays everything to the wallet again.
+    pub fn set_payout_splits(
+        env: Env,
+        employee_id: u64,
+        splits: Vec<PayoutSplit>,
+    ) -> Result<(), Error> {
+        let employee = load_employee(&env, employee_id)?;
+        employee.wallet_address.require_auth();
+
+        if splits.len() > MAX_PAYOUT_SPLITS {
+            return Err(Error::InvalidSplit);
+        }
+
+        let mut total_percent: u64 = 0;
+        for split in splits.iter() {
+            if split.destination == employee.wallet_address && split.asset == PayoutAsset::Source {
+                return Err(Error::InvalidSplit);
+            }
+            match split.share {
+                SplitShare::Percent(0) | SplitShare::Fixed(0) => return Err(Error::InvalidSplit),
+                SplitShare::Percent(percent) => total_percent += percent as u64,
+                SplitShare::Fixed(_) => {}
+            }
+        }
+        if total_percent > BASIS_POINTS {
+            return Err(Error::InvalidSplit);
+        }
+
+        let key = DataKey::PayoutSplits(employee_id);
+        let had_splits = env.storage().persistent().has(&key);
+        let has_splits = !splits.is_empty();
+        if !has_splits {
+            env.storage().persistent().remove(&key);
+        } else {
+            env.storage().persistent().set(&key, &splits);
+        }
+
+        // Lets releases skip split lookups for employers that have none
+        if had_splits != has_splits {
+            let count_key = DataKey::SplitEmployeeCount(employee.employer.clone());
+            let count: u32 = env.storage().persistent().get(&count_key).unwrap_or(0);
+            let count = if had_splits { count.saturating_sub(1) } else { count + 1 };
+            env.storage().persistent().set(&count_key, &count);
+        }
+
+        // Emit event
+        env.events().publish(
+            ("payout_splits_set",),
+            (employee_id, splits.len())
+        );
+
+        Ok(())
+    }
+
+    /// Get an employee's payout splits
+    pub fn get_payout_splits(
+        env: Env,
+        employee_id: u64,
+    ) -> Vec<PayoutSplit> {
+        env.storage().persistent()
+            .get(&DataKey::PayoutSplits(employee_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// How a payroll release of `amount` to an employee would be split
+    pub fn preview_payout(
+        env: Env,
+        employee_id: u64,
+        amount: u64,
+    ) -> Result<Vec<Payout>, Error> {
+        let employee = load_employee(&env, employee_id)?;
+        Ok(split_payout(&env, &employee, amount, true))
+    }
+
+    /// Get one page of an employee's change history, oldest first
+    pub fn get_employee_history(
+        env: Env,
+        employee_id: u64,
+        page: u32,
+    ) -> Vec<EmployeeHistoryEntry> {
+        env.storage().persistent()
+            .get(&DataKey::EmployeeHistory(employee_id, page))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Remove/terminate an employee
+    pub fn remove_employee(
+        env: Env,
+        employer: Address,
+        employee_id: u64,
+    ) -> Result<(), Error> {
+        employer.require_auth();
+
+        let employee_key = DataKey::Employee(employee_id);
+        let mut employee = load_employee(&env, employee_id)?;
+
+        // Verify employer owns this employee
+        if employee.employer != employer {
+            return Err(Error::NotAuthorized);
+        }
+
+        // Mark as terminated instead of deleting
+        check_status_transition(&employee.status, &EmployeeStatus::Terminated)?;
+        terminate_employee(&env, &employer, &mut employee);
+        env.storage().persistent().set(&employee_key, &employee);
+
+        // Emit event
+        env.events().publish(
+            ("employee_terminated",),
+            (employee_id, employer)
+        );
+
+        Ok(())
+    }
+
+    /// Rehire a terminated employee with a new start date, which cannot be
+    /// later than now
+    pub fn rehire_employee(
+        env: Env,
+        employer: Address,
+        employee_id: u64,
+        start_date: u64,
+    ) -> Result<(), Error> {
+        employer.require_auth();
+
+        // Check circuit breaker
+        let breaker_active: bool = env.storage().instance().get(&CIRCUIT_BREAKER).unwrap_or(false);
+        if breaker_active {
+            return Err(Error::CircuitBreakerActive);
+        }
+
+        let employee_key = DataKey::Employee(employee_id);
+        let mut employee = load_employee(&env, employee_id)?;
+
+        // Verify employer owns this employee
+        if employee.employer != employer {
+            return Err(Error::NotAuthorized);
+        }
+
+        // Rehiring is the only way out of Terminated
+        if employee.status != EmployeeStatus::Terminated {
+            return Err(Error::InvalidStatusTransition);
+        }
+
+        // Offboarding needs a last pay date between the start date and now
+        let current_time = env.ledger().timestamp();
+        if start_date > current_time {
+            return Err(Error::InvalidSchedule);
+        }
+
+        let change = EmployeeChange::Status(EmployeeStatus::Terminated, EmployeeStatus::Active);
+        record_employee_change(&env, employee_id, change, start_date, &employer);
+        move_employee_count(&env, &employer, Some(EmployeeStatus::Terminated), EmployeeStatus::Active);
+        employee.status = EmployeeStatus::Active;
+        employee.start_date = start_date;
+        employee.updated_at = current_time;
+
+        env.storage().persistent().set(&employee_key, &employee);
+
+        // Emit event
+        env.events().publish(
+            ("employee_rehired",),
+            (employee_id, employer, start_date)
+        );
+
+        Ok(())
+    }
+
+    /// Terminate an employee and settle their final pay
+    /// Active streams from this employer are cancelled: accrued funds go to
+    /// the employee and the rest back to the employer. Salary earned since
+    /// `last_pay_date` is prorated by payment schedule and either paid now
+    /// or escrowed in a new payroll in `asset`.
+    pub fn offboard_employee(
+        env: Env,
+        employer: Address,
+        employee_id: u64,
+        last_pay_date: u64,
+        final_pay_mode: FinalPayMode,
+        asset: Address,
+    ) -> Result<OffboardingSummary, Error> {
+        employer.require_auth();
+
+        // Check circuit breaker
+        let breaker_active: bool = env.storage().instance().get(&CIRCUIT_BREAKER).unwrap_or(false);
+        if breaker_active {
+            return Err(Error::CircuitBreakerActive);
+        }
+
+        let employee_key = DataKey::Employee(employee_id);
+        let mut employee = load_employee(&env, employee_id)?;
+
+        // Verify employer owns this employee
+        if employee.employer != employer {
+            return Err(Error::NotAuthorized);
+        }
+
+        // Final pay is settled once; a terminated employee has had theirs
+        if employee.status == EmployeeStatus::Terminated {
+            return Err(Error::InvalidStatusTransition);
+        }
+
+        let current_time = env.ledger().timestamp();
+        if last_pay_date > current_time || last_pay_date < employee.start_date {
+            return Err(Error::InvalidSchedule);
+        }
+
+        // Cancel this employer's active streams to the employee
+        let mut summary = OffboardingSummary {
+            employee_id,
+            streams_cancelled: 0,
+            stream_settled: 0,
+            stream_refunded: 0,
+            final_pay: 0,
+            final_pay_payroll: None,
+        };
+        let stream_ids: Vec<u64> = env.storage().persistent()
+            .get(&DataKey::RecipientStreams(employee.wallet_address.clone()))
+            .unwrap_or(Vec::new(&env));
+        for stream_id in stream_ids.iter() {
+            let stream_key = DataKey::Stream(stream_id);
+            let mut stream: StreamData = match env.storage().persistent().get(&stream_key) {
+                Some(stream) => stream,
+                None => continue,
+            };
+            if !stream.active || stream.from != employer {
+                continue;
+            }
+
+            // In a real implementation, these would transfer tokens
+            let vested = stream_vested(&stream, current_time);
+            summary.stream_settled += vested.saturating_sub(stream.total_withdrawn);
+            summary.stream_refunded += stream.total_deposited.saturating_sub(vested);
+            summary.streams_cancelled += 1;
+
+            stream.total_withdrawn = vested;
+            stream.total_deposited = vested;
+            stream.end_time = current_time;
+            stream.active = false;
+            env.storage().persistent().set(&stream_key, &stream);
+            remove_from_index(&env, &DataKey::RecipientStreams(stream.to.clone()), stream_id);
+
+            if let Some(payroll_id) = stream.payroll_id {
+                settle_payroll_stream(&env, payroll_id, stream_id, vested);
+            }
+        }
+
+        // Prorate salary earned since the last pay date
+        summary.final_pay = prorated_salary(&employee, current_time - last_pay_date);
+        if summary.final_pay > 0 && final_pay_mode == FinalPayMode::Escrow {
+            let recipients = Vec::from_array(
+                &env,
+                [Recipient {
+                    address: employee.wallet_address.clone(),
+                    amount: summary.final_pay,
+                    paid: false,
+                    stream_id: None,
+                }],
+            );
+            let payroll_id = store_payroll(
+                &env,
+                employer.clone(),
+                recipients,
+                asset,
+                ScheduleType::Immediate,
+                None,
+                None,
+            )?;
+            summary.final_pay_payroll = Some(payroll_id);
+        }
+        // FinalPayMode::Pay: in a real implementation, this would transfer tokens
+
+        terminate_employee(&env, &employer, &mut employee);
+        env.storage().persistent().set(&employee_key, &employee);
+
+        // Emit event
+        env.events().publish(
+            ("employee_offboarded",),
+            (employee_id, employer, summary.clone())
+        );
+
+        Ok(summary)
+    }
+
+    /// Get employee count for an employer
+    pub fn get_employee_count(
+        env: Env,
+        employer: Address,
+    ) -> u64 {
+        // Count only active employees
+        Self::get_employee_count_by_status(env, employer, EmployeeStatus::Active)
+    }
+
+    /// Get the number of an employer's employees in a given status
+    pub fn get_employee_count_by_status(
+        env: Env,
+        employer: Address,
+        status: EmployeeStatus,
+    ) -> u64 {
+        env.storage().persistent()
+            .get(&DataKey::EmployeeCount(employer, status))
+            .unwrap_or(0)
+    }
+}
+
+// =============================================================================
+// INTERNAL HELPERS
+// =============================================================================
+
+/// Validate and store a new payroll, returning its id
+fn store_payroll(
+    env: &Env,
+    employer: Address,
+    recipients: Vec<Recipient>,
+    asset: Address,
+    schedule_type: ScheduleType,
+    release_time: Option<u64>,
+    stream_rate: Option<u64>,
+) -> Result<u64, Error> {
+    // Validate recipients and calculate total amount
+    let total_amount = validate_recipients(env, &recipients)?;
+
+    // Streaming payrolls pay each recipient through a stream at this rate
+    if schedule_type == ScheduleType::Streaming && stream_rate.unwrap_or(0) == 0 {
+        return Err(Error::InvalidAmount);
+    }
+
+    // Get next payroll ID
+    let mut counter: u64 = env.storage().instance().get(&PAYROLL_COUNTER).unwrap_or(0);
+    counter += 1;
+    env.storage().instance().set(&PAYROLL_COUNTER, &counter);
+
+    // Create payroll data
+    let payroll = PayrollData {
+        employer: employer.clone(),
+        recipients,
+        total_amount,
+        deposited_amount: 0,
+        asset,
+        status: PayrollStatus::Created,
+        schedule_type,
+        release_time: release_time.unwrap_or(0),
+        created_at: env.ledger().timestamp(),
+        stream_rate,
+        release_cursor: 0,
+    };
+
+    // Store payroll
+    env.storage().persistent().set(&counter, &payroll);
+
+    // Index by employer and by recipient
+    push_index(env, &DataKey::EmployerPayrolls(employer.clone()), counter);
+    for recipient in payroll.recipients.iter() {
+        push_index(env, &DataKey::RecipientPayrolls(recipient.address), counter);
+    }
+
+    // Emit event
+    env.events().publish(
+        ("payroll_created",),
+        (counter, employer, total_amount)
+    );
+
+    Ok(counter)
+}
+
+/// Check a caller-supplied recipient list and return its total amount.
+/// Recipients must be unique, non-zero and not carry payment state.
+fn validate_recipients(env: &Env, recipients: &Vec<Recipient>) -> Result<u64, Error> {
+    if recipients.is_empty() {
+        return Err(Error::InvalidRecipients);
+    }
+    if recipients.len() > MAX_RECIPIENTS {
+        return Err(Error::TooManyRecipients);
+    }
+
+    let mut seen: Map<Address, bool> = Map::new(env);
+    let mut total_amount: u64 = 0;
+    for recipient in recipients.iter() {
+        if recipient.amount == 0 {
+            return Err(Error::ZeroRecipientAmount);
+        }
+        if recipient.paid {
+            return Err(Error::RecipientAlreadyPaid);
+        }
+        if recipient.stream_id.is_some() {
+            return Err(Error::RecipientStreamPreset);
+        }
+        if seen.contains_key(recipient.address.clone()) {
+            return Err(Error::DuplicateRecipient);
+        }
+        seen.set(recipient.address.clone(), true);
+
+        total_amount = total_amount
+            .checked_add(recipient.amount)
+            .ok_or(Error::InvalidAmount)?;
+    }
+
+    Ok(total_amount)
+}
+
+/// Position of `address` in a recipient list
+fn find_recipient(recipients: &Vec<Recipient>, address: &Address) -> Option<u32> {
+    recipients.iter().position(|r| r.address == *address).map(|i| i as u32)
+}
+
+/// Pay up to `max_count` recipients starting at the payroll's release cursor.
+/// The recipient list is updated in place and written back once per call.
+fn release_recipients(
+    env: &Env,
+    payroll_id: u64,
+    caller: Address,
+    max_count: u32,
+) -> Result<u32, Error> {
+    caller.require_auth();
+
+    let mut payroll: PayrollData = env.storage().persistent()
+        .get(&payroll_id)
+        .ok_or(Error::PayrollNotFound)?;
+
+    // Verify authorization
+    if caller != payroll.employer {
+        return Err(Error::NotAuthorized);
+    }
+
+    // Check if payroll is funded (or part-way through a batched release)
+    if payroll.status != PayrollStatus::Funded && payroll.status != PayrollStatus::Releasing {
+        return Err(Error::PayrollNotFunded);
+    }
+
+    // Check timing for scheduled releases
+    if payroll.schedule_type == ScheduleType::Scheduled
+        && env.ledger().timestamp() < payroll.release_time
+    {
+        return Err(Error::TooEarly);
+    }
+
+    // Streaming payrolls whose streams are all open only settle via withdrawals
+    let total = payroll.recipients.len();
+    if payroll.release_cursor >= total && payroll.status == PayrollStatus::Releasing {
+        return Ok(0);
+    }
+
+    payroll.status = PayrollStatus::Releasing;
+    let streaming = payroll.schedule_type == ScheduleType::Streaming;
+    let stream_rate = payroll.stream_rate.unwrap_or(0);
+
+    let may_have_advances = may_have_advances(env, &payroll);
+    let may_have_splits = env.storage().persistent()
+        .get::<DataKey, u32>(&DataKey::SplitEmployeeCount(payroll.employer.clone()))
+        .unwrap_or(0) > 0;
+    let deduction_rules: Vec<DeductionRule> = if streaming {
+        Vec::new(env)
+    } else {
+        env.storage().persistent()
+            .get(&DataKey::DeductionRules(payroll.employer.clone()))
+            .unwrap_or(Vec::new(env))
+    };
+
+    // Process payments to recipients
+    let mut processed = 0u32;
+    let mut index = payroll.release_cursor;
+    while index < total && processed < max_count {
+        let mut recipient = payroll.recipients.get_unchecked(index);
+        if streaming && recipient.stream_id.is_none() {
+            // Fund one stream per recipient out of the payroll escrow
+            let duration = recipient.amount.div_ceil(stream_rate);
+            let stream_id = open_stream(
+                env,
+                payroll.employer.clone(),
+                recipient.address.clone(),
+                stream_rate,
+                duration,
+                recipient.amount,
+                Some(payroll_id),
+                linear_schedule(),
+                payroll.asset.clone(),
+            )?;
+            recipient.stream_id = Some(stream_id);
+            payroll.recipients.set(index, recipient);
+        } else if !streaming && !recipient.paid {
+            // Wages drawn early come out of this payment
+            let advanced = if may_have_advances {
+                advanced_amount(env, payroll_id, &recipient.address)
+            } else {
+                0
+            };
+            if advanced > 0 {
+                env.events().publish(
+                    ("advance_deducted",),
+                    (payroll_id, recipient.address.clone(), advanced)
+                );
+            }
+
+            // Withhold deductions and record the breakdown
+            if advanced > 0 || !deduction_rules.is_empty() {
+                let payslip = withhold_deductions(env, payroll_id, &recipient, &deduction_rules, advanced);
+                recipient.amount = payslip.net;
+            }
+
+            // In a real implementation, this would transfer tokens
+            // For now, we just mark as paid
+            recipient.paid = true;
+
+            // Emit payment event
+            env.events().publish(
+                ("payment_released",),
+                (payroll_id, recipient.address.clone(), recipient.amount)
+            );
+            if may_have_splits {
+                pay_employee_splits(env, &payroll.employer, &recipient.address, recipient.amount, true);
+            }
+            remove_from_index(env, &DataKey::RecipientPayrolls(recipient.address.clone()), payroll_id);
+
+            payroll.recipients.set(index, recipient);
+        }
+        processed += 1;
+        index += 1;
+    }
+    payroll.release_cursor = index;
+
+    let remaining = total - index;
+    if remaining == 0 && !streaming {
+        // Mark as completed
+        payroll.status = PayrollStatus::Completed;
+    }
+    env.storage().persistent().set(&payroll_id, &payroll);
+
+    if remaining == 0 && streaming {
+        // Completion is reached once every recipient's stream is drained
+        env.events().publish(
+            ("payroll_streams_opened",),
+            (payroll_id, total)
+        );
+    } else if remaining == 0 {
+        // Emit completion event
+        env.events().publish(
+            ("payroll_completed",),
+            (payroll_id, payroll.total_amount)
+        );
+    } else {
+        env.events().publish(
+            ("payroll_batch_released",),
+            (payroll_id, processed, remaining)
+        );
+    }
+
+    Ok(remaining)
+}
+
+/// Amount a share takes from a gross payment, capped at what is left
+fn share_of(share: &SplitShare, gross: u64, remaining: u64) -> u64 {
+    match *share {
+        SplitShare::Percent(percent) => {
+            (gross as u128 * percent as u128 / BASIS_POINTS as u128) as u64
+        }
+        SplitShare::Fixed(fixed) => fixed,
+    }
+    .min(remaining)
+}
+
+/// Total an employer's deduction rules withhold from a gross payment
+fn total_deductions(env: &Env, employer: &Address, gross: u64) -> u64 {
+    let rules: Vec<DeductionRule> = env.storage().persistent()
+        .get(&DataKey::DeductionRules(employer.clone()))
+        .unwrap_or(Vec::new(env));
+    let mut remaining = gross;
+    for rule in rules.iter() {
+        remaining -= share_of(&rule.share, gross, remaining);
+    }
+    gross - remaining
+}
+
+/// Split a recipient's gross payment into withholdings and net pay,
+/// paying out each deduction and storing the payslip. An advance was
+/// already paid out of this payment, so deductions are capped at what it
+/// leaves and the payment never pays out more than gross; whatever the
+/// rules owed beyond that is recorded on the payslip as a shortfall.
+fn withhold_deductions(
+    env: &Env,
+    payroll_id: u64,
+    recipient: &Recipient,
+    rules: &Vec<DeductionRule>,
+    advanced: u64,
+) -> Payslip {
+    let gross = recipient.amount;
+    let mut remaining = gross.saturating_sub(advanced);
+    let mut owed_remaining = gross;
+    let mut shortfall = 0u64;
+    let mut deductions = Vec::new(env);
+    for rule in rules.iter() {
+        let owed = share_of(&rule.share, gross, owed_remaining);
+        owed_remaining -= owed;
+        let amount = owed.min(remaining);
+        shortfall += owed - amount;
+        if amount == 0 {
+            continue;
+        }
+        remaining -= amount;
+
+        // In a real implementation, this would transfer tokens
+        env.events().publish(
+            ("deduction_withheld",),
+            (payroll_id, recipient.address.clone(), rule.kind.clone(), rule.destination.clone(), amount)
+        );
+        deductions.push_back(DeductionLine {
+            kind: rule.kind,
+            destination: rule.destination,
+            amount,
+        });
+    }
+
+    let payslip = Payslip {
+        payroll_id,
+        recipient: recipient.address.clone(),
+        gross,
+        deductions,
+        advance: advanced,
+        net: remaining,
+        shortfall,
+        paid_at: env.ledger().timestamp(),
+    };
+    env.storage().persistent().set(&DataKey::Payslip(payroll_id, recipient.address.clone()), &payslip);
+
+    // Emit event
+    env.events().publish(("payslip",), payslip.clone());
+
+    payslip
+}
+
+/// Whether any advance can have been drawn against a payroll: only
+/// scheduled and recurring payrolls under an employer policy allow them
+fn may_have_advances(env: &Env, payroll: &PayrollData) -> bool {
+    (payroll.schedule_type == ScheduleType::Scheduled || payroll.schedule_type == ScheduleType::Recurring)
+        && env.storage().persistent().has(&DataKey::AdvancePolicy(payroll.employer.clone()))
+}
+
+/// Total advanced to a recipient against a payroll
+fn advanced_amount(env: &Env, payroll_id: u64, recipient: &Address) -> u64 {
+    env.storage().persistent()
+        .get(&DataKey::Advance(payroll_id, recipient.clone()))
+        .unwrap_or(0)
+}
+
+/// How much more a recipient may draw early from a payroll. The period is
+/// the employee's pay period ending at the payroll's release time; wages
+/// accrue linearly across it. Advances never exceed pay after deductions.
+fn advance_limit(
+    env: &Env,
+    payroll_id: u64,
+    payroll: &PayrollData,
+    recipient: &Address,
+) -> Result<u64, Error> {
+    let policy: AdvancePolicy = env.storage().persistent()
+        .get(&DataKey::AdvancePolicy(payroll.employer.clone()))
+        .ok_or(Error::AdvanceNotAvailable)?;
+
+    if payroll.schedule_type != ScheduleType::Scheduled && payroll.schedule_type != ScheduleType::Recurring {
+        return Err(Error::AdvanceNotAvailable);
+    }
+    if payroll.status != PayrollStatus::Funded {
+        return Err(Error::PayrollNotFunded);
+    }
+    let current_time = env.ledger().timestamp();
+    if current_time >= payroll.release_time {
+        return Err(Error::AdvanceNotAvailable);
+    }
+
+    let index = find_recipient(&payroll.recipients, recipient).ok_or(Error::RecipientNotFound)?;
+    let amount = payroll.recipients.get_unchecked(index).amount;
+
+    // Only the employer's active employees can draw early
+    let employee_id: u64 = env.storage().persistent()
+        .get(&DataKey::EmployeeByWallet(payroll.employer.clone(), recipient.clone()))
+        .ok_or(Error::AdvanceNotAvailable)?;
+    let employee = load_employee(env, employee_id)?;
+    if employee.status != EmployeeStatus::Active {
+        return Err(Error::AdvanceNotAvailable);
+    }
+
+    let period = pay_period_seconds(&employee.payment_schedule);
+    let period_start = payroll.release_time.saturating_sub(period);
+    let elapsed = current_time.saturating_sub(period_start).min(period);
+    let accrued = amount as u128 * elapsed as u128 / period as u128;
+    let limit = (accrued * policy.max_percent as u128 / BASIS_POINTS as u128) as u64;
+
+    // Leave enough of the payment to cover the employer's deductions
+    let after_deductions = amount - total_deductions(env, &payroll.employer, amount);
+    let limit = limit.min(policy.period_cap).min(after_deductions);
+    Ok(limit.saturating_sub(advanced_amount(env, payroll_id, recipient)))
+}
+
+/// Seconds in one pay period for an employee's payment schedule
+fn pay_period_seconds(payment_schedule: &String) -> u64 {
+    const DAY: u64 = 24 * 60 * 60;
+    let env = payment_schedule.env();
+    if *payment_schedule == String::from_str(env, "weekly") {
+        7 * DAY
+    } else if *payment_schedule == String::from_str(env, "bi-weekly") {
+        14 * DAY
+    } else {
+        30 * DAY
+    }
+}
+
+/// Salary earned over `elapsed` seconds at the employee's per-period rate
+fn prorated_salary(employee: &Employee, elapsed: u64) -> u64 {
+    let period = pay_period_seconds(&employee.payment_schedule);
+    let earned = employee.salary as u128 * elapsed as u128 / period as u128;
+    earned.min(u64::MAX as u128) as u64
+}
+
+/// Allowed employee status changes outside of rehiring:
+/// Active <-> Inactive and Active/Inactive -> Terminated. Staying in the
+/// same status is a no-op.
+fn check_status_transition(from: &EmployeeStatus, to: &EmployeeStatus) -> Result<(), Error> {
+    match (from, to) {
+        (EmployeeStatus::Active, _) | (EmployeeStatus::Inactive, _) => Ok(()),
+        (EmployeeStatus::Terminated, EmployeeStatus::Terminated) => Ok(()),
+        (EmployeeStatus::Terminated, _) => Err(Error::InvalidStatusTransition),
+    }
+}
+
+/// Mark an employee terminated, recording the change; the caller saves it
+fn terminate_employee(env: &Env, employer: &Address, employee: &mut Employee) {
+    let current_time = env.ledger().timestamp();
+    if employee.status != EmployeeStatus::Terminated {
+        let change = EmployeeChange::Status(employee.status.clone(), EmployeeStatus::Terminated);
+        record_employee_change(env, employee.id, change, current_time, employer);
+    }
+    move_employee_count(env, employer, Some(employee.status.clone()), EmployeeStatus::Terminated);
+    employee.status = EmployeeStatus::Terminated;
+    employee.updated_at = current_time;
+}
+
+/// Point an employee, their active streams from the employer and their
+/// unpaid share of open payrolls at a new wallet; saves the employee.
+/// Funds already accrued in a redirected stream follow it to the new wallet.
+/// Non-transferable streams only move when the employer consented.
+fn apply_wallet_change(
+    env: &Env,
+    employee: &mut Employee,
+    new_wallet: &Address,
+    employer_consented: bool,
+) -> Result<(), Error> {
+    let old_wallet = employee.wallet_address.clone();
+    let employer = employee.employer.clone();
+    let current_time = env.ledger().timestamp();
+
+    // The new wallet may have been taken since the change was requested
+    let new_wallet_key = DataKey::EmployeeByWallet(employer.clone(), new_wallet.clone());
+    if env.storage().persistent().has(&new_wallet_key) {
+        return Err(Error::DuplicateEmployee);
+    }
+    env.storage().persistent().remove(&DataKey::EmployeeByWallet(employer.clone(), old_wallet.clone()));
+    env.storage().persistent().set(&new_wallet_key, &employee.id);
+    remove_from_index(env, &DataKey::WalletEmployees(old_wallet.clone()), employee.id);
+    push_index(env, &DataKey::WalletEmployees(new_wallet.clone()), employee.id);
+
+    // Redirect active streams
+    let old_streams_key = DataKey::RecipientStreams(old_wallet.clone());
+    let stream_ids: Vec<u64> = env.storage().persistent()
+        .get(&old_streams_key)
+        .unwrap_or(Vec::new(env));
+    for stream_id in stream_ids.iter() {
+        let stream_key = DataKey::Stream(stream_id);
+        let mut stream: StreamData = match env.storage().persistent().get(&stream_key) {
+            Some(stream) => stream,
+            None => continue,
+        };
+        if !stream.active || stream.from != employer {
+            continue;
+        }
+        if !stream.transferable && !employer_consented {
+            continue;
+        }
+        stream.to = new_wallet.clone();
+        env.storage().persistent().set(&stream_key, &stream);
+        remove_from_index(env, &old_streams_key, stream_id);
+        push_index(env, &DataKey::RecipientStreams(new_wallet.clone()), stream_id);
+    }
+
+    // Redirect unpaid amounts in this employer's payrolls that can still pay out
+    let old_payrolls_key = DataKey::RecipientPayrolls(old_wallet.clone());
+    let payroll_ids: Vec<u64> = env.storage().persistent()
+        .get(&old_payrolls_key)
+        .unwrap_or(Vec::new(env));
+    for payroll_id in payroll_ids.iter() {
+        let mut payroll: PayrollData = match env.storage().persistent().get(&payroll_id) {
+            Some(payroll) => payroll,
+            None => continue,
+        };
+        if payroll.employer != employer {
+            continue;
+        }
+        if payroll.status == PayrollStatus::Completed || payroll.status == PayrollStatus::Cancelled {
+            continue;
+        }
+        // Leave payrolls that already pay the new wallet alone
+        if find_recipient(&payroll.recipients, new_wallet).is_some() {
+            continue;
+        }
+        let index = match find_recipient(&payroll.recipients, &old_wallet) {
+            Some(index) => index,
+            None => continue,
+        };
+        let mut recipient = payroll.recipients.get(index).unwrap();
+        if recipient.paid || recipient.stream_id.is_some() {
+            continue;
+        }
+        recipient.address = new_wallet.clone();
+        payroll.recipients.set(index, recipient);
+        env.storage().persistent().set(&payroll_id, &payroll);
+        remove_from_index(env, &old_payrolls_key, payroll_id);
+        push_index(env, &DataKey::RecipientPayrolls(new_wallet.clone()), payroll_id);
+
+        // Wages already drawn early still come out of this payment
+        let advance_key = DataKey::Advance(payroll_id, old_wallet.clone());
+        if let Some(advanced) = env.storage().persistent().get::<DataKey, u64>(&advance_key) {
+            env.storage().persistent().remove(&advance_key);
+            env.storage().persistent().set(&DataKey::Advance(payroll_id, new_wallet.clone()), &advanced);
+        }
+    }
+
+    let change = EmployeeChange::Wallet(old_wallet.clone(), new_wallet.clone());
+    record_employee_change(env, employee.id, change, current_time, &employer);
+    employee.wallet_address = new_wallet.clone();
+    employee.updated_at = current_time;
+    env.storage().persistent().set(&DataKey::Employee(employee.id), employee);
+
+    // Emit event
+    env.events().publish(
+        ("wallet_changed",),
+        (employee.id, old_wallet, new_wallet.clone())
+    );
+
+    Ok(())
+}
+
+/// Break a payment to an employee into its payout legs. Without splits
+/// there is a single leg paying everything to the employee's wallet.
+/// Fixed splits only apply to payroll releases (`release`), so they are
+/// taken once per pay period rather than from every stream withdrawal.
+fn split_payout(env: &Env, employee: &Employee, amount: u64, release: bool) -> Vec<Payout> {
+    let splits: Vec<PayoutSplit> = env.storage().persistent()
+        .get(&DataKey::PayoutSplits(employee.id))
+        .unwrap_or(Vec::new(env));
+
+    let mut payouts = Vec::new(env);
+    let mut remaining = amount;
+    for split in splits.iter() {
+        if !release && matches!(split.share, SplitShare::Fixed(_)) {
+            continue;
+        }
+        let share = share_of(&split.share, amount, remaining);
+        if share == 0 {
+            continue;
+        }
+        remaining -= share;
+        payouts.push_back(Payout {
+            employee_id: employee.id,
+            destination: split.destination,
+            asset: split.asset,
+            amount: share,
+        });
+    }
+    if remaining > 0 {
+        payouts.push_back(Payout {
+            employee_id: employee.id,
+            destination: employee.wallet_address.clone(),
+            asset: PayoutAsset::Source,
+            amount: remaining,
+        });
+    }
+    payouts
+}
+
+/// Route a payment made to `wallet` through the employee's payout splits,
+/// if the employer has an employee paid at that wallet who set any
+fn pay_employee_splits(env: &Env, employer: &Address, wallet: &Address, amount: u64, release: bool) {
+    let employee_id: u64 = match env.storage().persistent()
+        .get(&DataKey::EmployeeByWallet(employer.clone(), wallet.clone()))
+    {
+        Some(employee_id) => employee_id,
+        None => return,
+    };
+    if !env.storage().persistent().has(&DataKey::PayoutSplits(employee_id)) {
+        return;
+    }
+    let employee = match load_employee(env, employee_id) {
+        Ok(employee) => employee,
+        Err(_) => return,
+    };
+
+    for payout in split_payout(env, &employee, amount, release).iter() {
+        // In a real implementation, this would transfer tokens, swapping
+        // into the payout's asset first when one is set
+        env.events().publish(("payout_split",), payout);
+    }
+}
+
+/// Asset contract the employer has mapped a currency code to
+fn currency_asset(env: &Env, employer: &Address, currency: &String) -> Result<Address, Error> {
+    env.storage().persistent()
+        .get(&DataKey::CurrencyAsset(employer.clone(), currency.clone()))
+        .ok_or(Error::UnmappedCurrency)
+}
+
+/// Whether the employer already has an employee with this wallet or email
+fn employee_exists(env: &Env, employer: &Address, wallet: &Address, email: &BytesN<32>) -> bool {
+    let wallet_key = DataKey::EmployeeByWallet(employer.clone(), wallet.clone());
+    let email_key = DataKey::EmployeeByEmail(employer.clone(), email.clone());
+    env.storage().persistent().has(&wallet_key) || env.storage().persistent().has(&email_key)
+}
+
+/// Save a new active employee under `employee_id` with its lookup indexes
+/// and status count, indexed by the email hash the caller checked. The
+/// caller adds the id to the employer's list and advances the employee
+/// counter.
+fn store_new_employee(
+    env: &Env,
+    employer: &Address,
+    employee_id: u64,
+    new_employee: NewEmployee,
+    email: BytesN<32>,
+) {
+    let current_time = env.ledger().timestamp();
+
+    let wallet_key = DataKey::EmployeeByWallet(employer.clone(), new_employee.wallet_address.clone());
+    let email_key = DataKey::EmployeeByEmail(employer.clone(), email);
+    env.storage().persistent().set(&wallet_key, &employee_id);
+    env.storage().persistent().set(&email_key, &employee_id);
+    push_index(env, &DataKey::WalletEmployees(new_employee.wallet_address.clone()), employee_id);
+
+    let employee = Employee {
+        id: employee_id,
+        employer: employer.clone(),
+        wallet_address: new_employee.wallet_address,
+        email: new_employee.email,
+        first_name: new_employee.first_name,
+        last_name: new_employee.last_name,
+        position: new_employee.position,
+        salary: new_employee.salary,
+        currency: new_employee.currency,
+        payment_schedule: new_employee.payment_schedule,
+        status: EmployeeStatus::Active,
+        created_at: current_time,
+        updated_at: current_time,
+        start_date: current_time,
+    };
+    env.storage().persistent().set(&DataKey::Employee(employee_id), &employee);
+    move_employee_count(env, employer, None, EmployeeStatus::Active);
+
+    // Emit event
+    env.events().publish(
+        ("employee_added",),
+        (employee_id, employer.clone(), employee.wallet_address, employee.email, employee.salary)
+    );
+}
+
+/// Hash of an email address, used to index employees without storing the
+/// address in the key. Surrounding whitespace is trimmed and ASCII letters
+/// lowercased first, so differently typed copies of one address collide.
+fn email_hash(env: &Env, email: &String) -> Result<BytesN<32>, Error> {
+    let len = email.len();
+    if len > MAX_EMAIL_LEN {
+        return Err(Error::InvalidEmail);
+    }
+    let mut buf = [0u8; MAX_EMAIL_LEN as usize];
+    let raw = &mut buf[..len as usize];
+    email.copy_into_slice(raw);
+    raw.make_ascii_lowercase();
+    Ok(env.crypto().sha256(&Bytes::from_slice(env, raw.trim_ascii())))
+}
+
+/// Load an employee with any due scheduled salary change applied
+fn load_employee(env: &Env, employee_id: u64) -> Result<Employee, Error> {
+    let employee_key = DataKey::Employee(employee_id);
+    let mut employee: Employee = env.storage().persistent()
+        .get(&employee_key)
+        .ok_or(Error::PayrollNotFound)?; // Reusing error for not found
+
+    let pending_key = DataKey::PendingSalary(employee_id);
+    if let Some(pending) = env.storage().persistent().get::<DataKey, PendingSalaryChange>(&pending_key) {
+        if pending.effective_at <= env.ledger().timestamp() {
+            employee.salary = pending.salary;
+            employee.updated_at = pending.effective_at;
+        }
+    }
+
+    Ok(employee)
+}
+
+/// Drop an employee's scheduled salary change. One that has not taken effect
+/// yet gets a cancellation entry so the history doesn't show it as applied.
+fn cancel_pending_salary(env: &Env, employee_id: u64, changed_by: &Address) {
+    let pending_key = DataKey::PendingSalary(employee_id);
+    if let Some(pending) = env.storage().persistent().get::<DataKey, PendingSalaryChange>(&pending_key) {
+        let current_time = env.ledger().timestamp();
+        if pending.effective_at > current_time {
+            let change = EmployeeChange::SalaryChangeCancelled(pending.salary, pending.effective_at);
+            record_employee_change(env, employee_id, change, current_time, changed_by);
+        }
+        env.storage().persistent().remove(&pending_key);
+    }
+}
+
+/// Append a change to an employee's paged history
+fn record_employee_change(
+    env: &Env,
+    employee_id: u64,
+    change: EmployeeChange,
+    effective_at: u64,
+    changed_by: &Address,
+) {
+    let len_key = DataKey::EmployeeHistoryLen(employee_id);
+    let len: u32 = env.storage().persistent().get(&len_key).unwrap_or(0);
+
+    let page_key = DataKey::EmployeeHistory(employee_id, len / HISTORY_PAGE_SIZE);
+    let mut entries: Vec<EmployeeHistoryEntry> = env.storage().persistent()
+        .get(&page_key)
+        .unwrap_or(Vec::new(env));
+    entries.push_back(EmployeeHistoryEntry {
+        change,
+        effective_at,
+        changed_by: changed_by.clone(),
+        recorded_at: env.ledger().timestamp(),
+    });
+    env.storage().persistent().set(&page_key, &entries);
+    env.storage().persistent().set(&len_key, &(len + 1));
+}
+
+/// Move one employee between an employer's per-status counters
+fn move_employee_count(
+    env: &Env,
+    employer: &Address,
+    from: Option<EmployeeStatus>,
+    to: EmployeeStatus,
+) {
+    if from.as_ref() == Some(&to) {
+        return;
+    }
+    if let Some(from) = from {
+        let key = DataKey::EmployeeCount(employer.clone(), from);
+        let count: u64 = env.storage().persistent().get(&key).unwrap_or(0);
+        env.storage().persistent().set(&key, &count.saturating_sub(1));
+    }
+    let key = DataKey::EmployeeCount(employer.clone(), to);
+    let count: u64 = env.storage().persistent().get(&key).unwrap_or(0);
+    env.storage().persistent().set(&key, &(count + 1));
+}
+
+/// Append an id to a list-valued index
+fn push_index(env: &Env, key: &DataKey, id: u64) {
+    let mut ids: Vec<u64> = env.storage().persistent()
+        .get(key)
+        .unwrap_or(Vec::new(env));
+    ids.push_back(id);
+    env.storage().persistent().set(key, &ids);
+}
+
+/// Remove an id from a list-valued index, if present
+fn remove_from_index(env: &Env, key: &DataKey, id: u64) {
+    let mut ids: Vec<u64> = env.storage().persistent()
+        .get(key)
+        .unwrap_or(Vec::new(env));
+    if let Some(index) = ids.first_index_of(id) {
+        ids.remove(index);
+        env.storage().persistent().set(key, &ids);
+    }
+}
+
+/// Up to `limit` ids of a list-valued index starting at position `start`
+fn index_page(env: &Env, key: &DataKey, start: u32, limit: u32) -> Vec<u64> {
+    let ids: Vec<u64> = env.storage().persistent()
+        .get(key)
+        .unwrap_or(Vec::new(env));
+    let start = start.min(ids.len());
+    let end = start.saturating_add(limit).min(ids.len());
+    ids.slice(start..end)
+}
+
+/// Default schedule: starts now, no cliff, linear accrual
+fn linear_schedule() -> StreamSchedule {
+    StreamSchedule {
+        start_time: None,
+        cliff_time: None,
+        curve: VestingCurve::Linear,
+    }
+}
+
+/// Create and store a new stream, returning its id
+fn open_stream(
+    env: &Env,
+    from: Address,
+    to: Address,
+    rate_per_sec: u64,
+    duration: u64,
+    total_amount: u64,
+    payroll_id: Option<u64>,
+    schedule: StreamSchedule,
+    asset: Address,
+) -> Result<u64, Error> {
+    let current_time = env.ledger().timestamp();
+    let start_time = schedule.start_time.unwrap_or(current_time);
+    if start_time < current_time {
+        return Err(Error::InvalidSchedule);
+    }
+
+    // Tranche streams end at their last unlock and do not accrue by rate
+    let (rate_per_sec, end_time) = match &schedule.curve {
+        VestingCurve::Linear => (rate_per_sec, start_time.saturating_add(duration)),
+        VestingCurve::Stepped(interval) => {
+            if *interval == 0 || *interval > duration {
+                return Err(Error::InvalidSchedule);
+            }
+            (rate_per_sec, start_time.saturating_add(duration))
+        }
+        VestingCurve::Tranches(tranches) => {
+            let mut last_unlock = start_time;
+            let mut sum: u64 = 0;
+            for (i, tranche) in tranches.iter().enumerate() {
+                if tranche.amount == 0
+                    || tranche.unlock_time < start_time
+                    || (i > 0 && tranche.unlock_time <= last_unlock)
+                {
+                    return Err(Error::InvalidSchedule);
                 }
+                last_unlock = tranche.unlock_time;
+                sum = sum.checked_add(tranche.amount).ok_or(Error::InvalidAmount)?;
+            }
+            if tranches.is_empty() || sum != total_amount {
+                return Err(Error::InvalidSchedule);
             }
+            (0, last_unlock)
         }
+    };
 
-        count
+    let cliff_time = schedule.cliff_time.unwrap_or(0);
+    if schedule.cliff_time.is_some() && (cliff_time < start_time || cliff_time > end_time) {
+        return Err(Error::InvalidSchedule);
     }
+
+    // Get next stream ID
+    let mut counter: u64 = env.storage().instance().get(&STREAM_COUNTER).unwrap_or(0);
+    counter += 1;
+    env.storage().instance().set(&STREAM_COUNTER, &counter);
+
+    let stream = StreamData {
+        from: from.clone(),
+        to: to.clone(),
+        rate_per_sec,
+        start_time,
+        end_time,
+        last_withdrawal: start_time,
+        total_deposited: total_amount,
+        total_withdrawn: 0,
+        active: true,
+        payroll_id,
+        cliff_time,
+        curve: schedule.curve,
+        settled_amount: 0,
+        paused: false,
+        paused_at: 0,
+        transferable: true,
+        asset,
+    };
+
+    // Store stream
+    let stream_key = DataKey::Stream(counter);
+    env.storage().persistent().set(&stream_key, &stream);
+    push_index(env, &DataKey::RecipientStreams(to.clone()), counter);
+
+    // Emit event
+    env.events().publish(
+        ("stream_started",),
+        (counter, from, to, rate_per_sec, end_time - start_time)
+    );
+
+    Ok(counter)
+}
+
+/// Time a stream accrues at: frozen at `paused_at` while paused
+fn stream_clock(stream: &StreamData, now: u64) -> u64 {
+    if stream.paused {
+        now.min(stream.paused_at)
+    } else {
+        now
+    }
+}
+
+/// Total amount of a stream accrued at `now`, ignoring any cliff
+fn stream_accrued(stream: &StreamData, now: u64) -> u64 {
+    let now = stream_clock(stream, now);
+    if now < stream.start_time {
+        return stream.settled_amount.min(stream.total_deposited);
+    }
+
+    let elapsed = now - stream.start_time;
+    let accrued = match &stream.curve {
+        VestingCurve::Linear => elapsed.saturating_mul(stream.rate_per_sec),
+        VestingCurve::Stepped(interval) => {
+            (elapsed / interval * interval).saturating_mul(stream.rate_per_sec)
+        }
+        VestingCurve::Tranches(tranches) => tranches
+            .iter()
+            .filter(|t| t.unlock_time <= now)
+            .map(|t| t.amount)
+            .sum(),
+    };
+
+    stream.settled_amount.saturating_add(accrued).min(stream.total_deposited)
+}
+
+/// Total amount of a stream vested at `now`, including already withdrawn funds
+fn stream_vested(stream: &StreamData, now: u64) -> u64 {
+    if stream_clock(stream, now) < stream.cliff_time {
+        return 0;
+    }
+    stream_accrued(stream, now)
+}
+
+/// Load an active stream that `from` is the sender of
+fn load_sender_stream(env: &Env, stream_key: &DataKey, from: &Address) -> Result<StreamData, Error> {
+    let stream: StreamData = env.storage().persistent()
+        .get(stream_key)
+        .ok_or(Error::StreamNotFound)?;
+
+    if stream.from != *from {
+        return Err(Error::NotAuthorized);
+    }
+
+    if !stream.active {
+        return Err(Error::StreamInactive);
+    }
+
+    Ok(stream)
+}
+
+/// Lock in what a stream has accrued so far and restart accrual from `now`,
+/// so its rate or deposit can change without affecting past accrual.
+/// Only linear streams can do this without disturbing their unlock schedule.
+fn settle_stream_accrual(stream: &mut StreamData, now: u64) -> Result<(), Error> {
+    if stream.curve != VestingCurve::Linear {
+        return Err(Error::InvalidSchedule);
+    }
+    if stream.paused {
+        return Err(Error::StreamPaused);
+    }
+    if now > stream.start_time {
+        stream.settled_amount = stream_accrued(stream, now);
+        stream.start_time = now;
+    }
+    Ok(())
+}
+
+/// When a linear stream will have accrued its whole deposit
+fn linear_end_time(stream: &StreamData) -> u64 {
+    let unaccrued = stream.total_deposited.saturating_sub(stream.settled_amount);
+    stream.start_time.saturating_add(unaccrued.div_ceil(stream.rate_per_sec))
+}
+
+/// Withdraw `amount` (or everything withdrawable) from a stream to `destination`
+/// The caller is responsible for authorising `to`.
+fn withdraw_from_stream(
+    env: &Env,
+    stream_id: u64,
+    to: Address,
+    amount: Option<u64>,
+    destination: Address,
+) -> Result<u64, Error> {
+    let stream_key = DataKey::Stream(stream_id);
+    let mut stream: StreamData = env.storage().persistent()
+        .get(&stream_key)
+        .ok_or(Error::StreamNotFound)?;
+
+    if !stream.active {
+        return Err(Error::StreamInactive);
+    }
+
+    if to != stream.to {
+        return Err(Error::NotAuthorized);
+    }
+
+    // Everything vested so far that has not been withdrawn yet
+    let current_time = env.ledger().timestamp();
+    let withdrawable = stream_balance_at(&stream, current_time).withdrawable;
+    let withdrawal_amount = match amount {
+        Some(requested) if requested > withdrawable => return Err(Error::InsufficientBalance),
+        Some(requested) => requested,
+        None => withdrawable,
+    };
+
+    if withdrawal_amount == 0 {
+        return Ok(0);
+    }
+
+    // Update stream
+    stream.total_withdrawn += withdrawal_amount;
+    stream.last_withdrawal = current_time;
+
+    // Deactivate if fully withdrawn
+    if stream.total_withdrawn >= stream.total_deposited {
+        stream.active = false;
+        remove_from_index(env, &DataKey::RecipientStreams(stream.to.clone()), stream_id);
+    }
+
+    env.storage().persistent().set(&stream_key, &stream);
+
+    // Emit event
+    env.events().publish(
+        ("stream_withdrawn",),
+        (stream_id, destination.clone(), withdrawal_amount)
+    );
+
+    // Splits only apply to pay sent to the recipient's own wallet
+    if destination == stream.to {
+        pay_employee_splits(env, &stream.from, &stream.to, withdrawal_amount, false);
+    }
+
+    // A drained payroll stream settles its recipient
+    if !stream.active {
+        if let Some(payroll_id) = stream.payroll_id {
+            settle_payroll_stream(env, payroll_id, stream_id, stream.total_withdrawn);
+        }
+    }
+
+    Ok(withdrawal_amount)
+}
+
+/// Timestamp at which the whole deposit of a stream has vested
+fn stream_fully_vested_at(stream: &StreamData, now: u64) -> u64 {
+    let vested_at = match &stream.curve {
+        VestingCurve::Linear => linear_end_time(stream),
+        VestingCurve::Stepped(interval) => {
+            let per_step = stream.rate_per_sec.saturating_mul(*interval);
+            stream.start_time
+                .saturating_add(stream.total_deposited.div_ceil(per_step).saturating_mul(*interval))
+        }
+        VestingCurve::Tranches(_) => stream.end_time,
+    };
+    let vested_at = vested_at.max(stream.cliff_time);
+
+    // A paused stream is assumed to resume immediately
+    if stream.paused && vested_at > stream.paused_at {
+        return vested_at.saturating_add(now.saturating_sub(stream.paused_at));
+    }
+    vested_at
+}
+
+/// Balance figures of a stream at `now`, as `withdraw_stream` sees them
+fn stream_balance_at(stream: &StreamData, now: u64) -> StreamBalance {
+    let accrued = stream_vested(stream, now);
+    let withdrawable = if stream.active {
+        accrued.saturating_sub(stream.total_withdrawn)
+    } else {
+        0
+    };
+
+    StreamBalance {
+        accrued,
+        withdrawn: stream.total_withdrawn,
+        withdrawable,
+        remaining: stream.total_deposited.saturating_sub(stream.total_withdrawn),
+        time_to_depletion: stream_fully_vested_at(stream, now).saturating_sub(now),
+    }
+}
+
+/// Mark the recipient paid by a drained or cancelled payroll stream, which
+/// paid them `paid` in total, and complete the payroll once every
+/// recipient's stream has been settled
+fn settle_payroll_stream(env: &Env, payroll_id: u64, stream_id: u64, paid: u64) {
+    let mut payroll: PayrollData = match env.storage().persistent().get(&payroll_id) {
+        Some(payroll) => payroll,
+        None => return,
+    };
+
+    let mut all_paid = true;
+    for (index, recipient) in payroll.recipients.iter().enumerate() {
+        if recipient.stream_id == Some(stream_id) && !recipient.paid {
+            let mut recipient = recipient.clone();
+            recipient.paid = true;
+            recipient.amount = paid;
+            env.events().publish(
+                ("payment_released",),
+                (payroll_id, recipient.address.clone(), recipient.amount)
+            );
+            remove_from_index(env, &DataKey::RecipientPayrolls(recipient.address.clone()), payroll_id);
+            payroll.recipients.set(index as u32, recipient);
+        } else if !recipient.paid {
+            all_paid = false;
+        }
+    }
+
+    if all_paid
+        && payroll.status == PayrollStatus::Releasing
+        && payroll.release_cursor >= payroll.recipients.len()
+    {
+        payroll.status = PayrollStatus::Completed;
+        env.events().publish(
+            ("payroll_completed",),
+            (payroll_id, payroll.total_amount)
+        );
+    }
+    env.storage().persistent().set(&payroll_id, &payroll);
 }
+
+mod test;
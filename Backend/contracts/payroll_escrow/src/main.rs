@@ -1,3 +1,287 @@
+//! PayrollEscrow command line helpers
+//!
+//! `import-employees` turns an HR spreadsheet exported as CSV into
+//! `soroban contract invoke` commands for the `add_employees` entrypoint.
+//! The CSV needs a header row naming these columns, in any order:
+//!
+//!     wallet_address,email,first_name,last_name,position,salary,currency,payment_schedule
+//!
+//! Rows are split into batches of at most `MAX_EMPLOYEE_BATCH` employees,
+//! one command per batch.
+
+use std::env;
+use std::fs;
+use std::process;
+
+/// Must match `MAX_EMPLOYEE_BATCH` in the contract
+const MAX_EMPLOYEE_BATCH: usize = 50;
+
+const COLUMNS: [&str; 8] = [
+    "wallet_address",
+    "email",
+    "first_name",
+    "last_name",
+    "position",
+    "salary",
+    "currency",
+    "payment_schedule",
+];
+
+const USAGE: &str = "usage: payroll_escrow import-employees <file.csv> --id <contract id> --employer <address> [--source <identity>] [--network <network>]";
+
 fn main() {
-    println!("PayrollEscrow Contract");
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("import-employees") => {
+            if let Err(message) = import_employees(&args[1..]) {
+                eprintln!("error: {}", message);
+                process::exit(1);
+            }
+        }
+        _ => {
+            println!("PayrollEscrow Contract");
+            println!("{}", USAGE);
+        }
+    }
+}
+
+/// Print one `add_employees` invocation per batch of CSV rows
+fn import_employees(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut contract_id = None;
+    let mut employer = None;
+    let mut source = None;
+    let mut network = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let target = match arg.as_str() {
+            "--id" => &mut contract_id,
+            "--employer" => &mut employer,
+            "--source" => &mut source,
+            "--network" => &mut network,
+            _ if path.is_none() && !arg.starts_with("--") => {
+                path = Some(arg.clone());
+                continue;
+            }
+            _ => return Err(format!("unexpected argument `{}`\n{}", arg, USAGE)),
+        };
+        *target = Some(args.next().ok_or_else(|| format!("{} needs a value", arg))?.clone());
+    }
+
+    let path = path.ok_or(USAGE)?;
+    let contract_id = contract_id.ok_or(USAGE)?;
+    let employer = employer.ok_or(USAGE)?;
+
+    let text = fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let employees = parse_employees(&text)?;
+    if employees.is_empty() {
+        return Err(format!("{} has no employee rows", path));
+    }
+
+    for batch in employees.chunks(MAX_EMPLOYEE_BATCH) {
+        println!(
+            "{}",
+            invoke_command(&contract_id, &employer, source.as_deref(), network.as_deref(), batch)
+        );
+    }
+
+    Ok(())
+}
+
+/// Build the `soroban contract invoke` command for one batch, quoting every
+/// caller-supplied value for the shell
+fn invoke_command(
+    contract_id: &str,
+    employer: &str,
+    source: Option<&str>,
+    network: Option<&str>,
+    batch: &[String],
+) -> String {
+    let mut command = format!("soroban contract invoke --id {}", shell_quote(contract_id));
+    if let Some(source) = source {
+        command.push_str(&format!(" --source {}", shell_quote(source)));
+    }
+    if let Some(network) = network {
+        command.push_str(&format!(" --network {}", shell_quote(network)));
+    }
+    command.push_str(&format!(
+        " -- add_employees --employer {} --employees {}",
+        shell_quote(employer),
+        shell_quote(&format!("[{}]", batch.join(","))),
+    ));
+    command
+}
+
+/// Wrap a value in single quotes for a POSIX shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Parse the CSV into one JSON `NewEmployee` object per row
+fn parse_employees(text: &str) -> Result<Vec<String>, String> {
+    let mut rows = parse_csv(text)?.into_iter();
+    let header = rows.next().ok_or("the CSV is empty")?;
+
+    // Position of each contract field in the spreadsheet's columns
+    let mut positions = Vec::with_capacity(COLUMNS.len());
+    for column in COLUMNS {
+        let position = header
+            .iter()
+            .position(|name| name.trim().eq_ignore_ascii_case(column))
+            .ok_or_else(|| format!("missing column `{}`", column))?;
+        positions.push(position);
+    }
+
+    let mut employees = Vec::new();
+    for (index, row) in rows.enumerate() {
+        // Header is line 1
+        let line = index + 2;
+        if row.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+
+        let mut fields = Vec::with_capacity(COLUMNS.len());
+        for (column, &position) in COLUMNS.iter().zip(&positions) {
+            let value = row.get(position).map(|field| field.trim()).unwrap_or("");
+            if value.is_empty() {
+                return Err(format!("line {}: `{}` is empty", line, column));
+            }
+            let value = if *column == "salary" {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("line {}: salary `{}` is not a whole number", line, value))?
+                    .to_string()
+            } else {
+                json_string(value)
+            };
+            fields.push(format!("\"{}\":{}", column, value));
+        }
+        employees.push(format!("{{{}}}", fields.join(",")));
+    }
+
+    Ok(employees)
+}
+
+/// Split CSV text into rows of fields, honouring quoted fields with
+/// embedded commas, newlines and doubled quotes
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Quote a value as a JSON string
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "wallet_address,email,first_name,last_name,position,salary,currency,payment_schedule\n";
+
+    #[test]
+    fn test_parse_csv_quoted_fields() {
+        let rows = parse_csv("a,\"b, c\",\"line\nbreak\"\r\n\"say \"\"hi\"\"\",,\n").unwrap();
+        assert_eq!(rows, vec![
+            vec!["a".to_string(), "b, c".to_string(), "line\nbreak".to_string()],
+            vec!["say \"hi\"".to_string(), String::new(), String::new()],
+        ]);
+        assert!(parse_csv("a,\"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_parse_employees() {
+        let csv = format!("{}GABC,o'brien@example.com,\"Pat \"\"P\"\"\",O,\"Dev, Senior\",5000,USDC,monthly\n\n", HEADER);
+        let employees = parse_employees(&csv).unwrap();
+        assert_eq!(employees, vec![
+            "{\"wallet_address\":\"GABC\",\"email\":\"o'brien@example.com\",\"first_name\":\"Pat \\\"P\\\"\",\
+             \"last_name\":\"O\",\"position\":\"Dev, Senior\",\"salary\":5000,\"currency\":\"USDC\",\
+             \"payment_schedule\":\"monthly\"}".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_employees_rejects_bad_rows() {
+        let missing = "wallet_address,email,first_name,last_name,position,salary,currency\n";
+        assert_eq!(parse_employees(missing).unwrap_err(), "missing column `payment_schedule`");
+
+        let bad_salary = format!("{}GABC,a@example.com,A,B,Dev,lots,USDC,monthly\n", HEADER);
+        assert_eq!(parse_employees(&bad_salary).unwrap_err(), "line 2: salary `lots` is not a whole number");
+
+        let empty_field = format!("{}GABC,,A,B,Dev,5000,USDC,monthly\n", HEADER);
+        assert_eq!(parse_employees(&empty_field).unwrap_err(), "line 2: `email` is empty");
+    }
+
+    #[test]
+    fn test_json_string_escaping() {
+        assert_eq!(json_string("it's"), "\"it's\"");
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+    }
+
+    #[test]
+    fn test_shell_quote_single_quotes() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote("o'brien"), "'o'\\''brien'");
+    }
+
+    #[test]
+    fn test_invoke_command_quotes_arguments() {
+        let batch = vec!["{\"email\":\"o'brien@example.com\"}".to_string()];
+        let command = invoke_command("CXYZ", "GEMP; rm -rf /", Some("alice's key"), None, &batch);
+        assert_eq!(
+            command,
+            "soroban contract invoke --id 'CXYZ' --source 'alice'\\''s key' \
+             -- add_employees --employer 'GEMP; rm -rf /' --employees '[{\"email\":\"o'\\''brien@example.com\"}]'"
+        );
+    }
 }